@@ -18,9 +18,9 @@ use crate::{
     item_tree::ItemTreeNode,
     nameres::DefMap,
     src::{HasChildSource, HasSource},
-    AdtId, AssocItemId, DefWithBodyId, EnumId, FieldId, GenericDefId, ImplId, ItemTreeLoc,
-    LifetimeParamId, Lookup, MacroId, ModuleDefId, ModuleId, TraitId, TypeOrConstParamId,
-    VariantId,
+    AdtId, AssocItemId, DefWithBodyId, EnumId, ExternBlockId, FieldId, GenericDefId, ImplId,
+    ItemContainerId, ItemTreeLoc, LifetimeParamId, Lookup, MacroId, ModuleDefId, ModuleId, TraitId,
+    TypeOrConstParamId, VariantId,
 };
 
 pub trait ChildBySource {
@@ -62,6 +62,53 @@ impl ChildBySource for ImplId {
     }
 }
 
+impl ChildBySource for ExternBlockId {
+    fn child_by_source_to(&self, db: &dyn DefDatabase, res: &mut DynMap, file_id: HirFileId) {
+        let loc = self.lookup(db);
+        // Foreign items are declared in the scope of the module containing the block, so pick
+        // out the ones that belong to this block. Items from macro calls in the block belong to
+        // it as well, but live in the calls' expansions.
+        let container = ItemContainerId::ExternBlockId(*self);
+        let def_map = loc.container.def_map(db);
+        let scope = &def_map[loc.container.local_id].scope;
+        scope.declarations().for_each(|item| match item {
+            ModuleDefId::FunctionId(id) if id.lookup(db).container == container => {
+                insert_item_loc(db, res, file_id, id, keys::FUNCTION)
+            }
+            ModuleDefId::StaticId(id) if id.lookup(db).container == container => {
+                insert_item_loc(db, res, file_id, id, keys::STATIC)
+            }
+            ModuleDefId::TypeAliasId(id) if id.lookup(db).container == container => {
+                insert_item_loc(db, res, file_id, id, keys::TYPE_ALIAS)
+            }
+            _ => (),
+        });
+
+        // Macro calls inside the block are recorded on the module scope as well, and can only be
+        // told apart from the module's other calls by their position in the block's file.
+        if loc.id.file_id() != file_id {
+            return;
+        }
+        let range = loc.ast_ptr(db).value.text_range();
+        scope.attr_macro_invocs().filter(|(id, _)| id.file_id == file_id).for_each(
+            |(ast_id, call_id)| {
+                let ptr = ast_id.to_ptr(db.upcast());
+                if range.contains_range(ptr.text_range()) {
+                    res[keys::ATTR_MACRO_CALL].insert(ptr, call_id);
+                }
+            },
+        );
+        scope.iter_macro_invoc().filter(|(id, _)| id.file_id == file_id).for_each(
+            |(ast_id, &call)| {
+                let ptr = ast_id.to_ptr(db.upcast());
+                if range.contains_range(ptr.text_range()) {
+                    res[keys::MACRO_CALL].insert(ptr, call);
+                }
+            },
+        );
+    }
+}
+
 impl ChildBySource for ModuleId {
     fn child_by_source_to(&self, db: &dyn DefDatabase, res: &mut DynMap, file_id: HirFileId) {
         let def_map = self.def_map(db);
@@ -77,6 +124,8 @@ impl ChildBySource for ItemScope {
         self.extern_crate_decls()
             .for_each(|ext| insert_item_loc(db, res, file_id, ext, keys::EXTERN_CRATE));
         self.use_decls().for_each(|ext| insert_item_loc(db, res, file_id, ext, keys::USE));
+        self.extern_blocks()
+            .for_each(|block| insert_item_loc(db, res, file_id, block, keys::EXTERN_BLOCK));
         self.unnamed_consts()
             .for_each(|konst| insert_item_loc(db, res, file_id, konst, keys::CONST));
         self.attr_macro_invocs().filter(|(id, _)| id.file_id == file_id).for_each(
@@ -217,8 +266,8 @@ impl ChildBySource for GenericDefId {
         let mut toc_idx_iter = generic_params.type_or_consts.iter().map(|(idx, _)| idx);
         let lts_idx_iter = generic_params.lifetimes.iter().map(|(idx, _)| idx);
 
-        // For traits the first type index is `Self`, skip it.
-        if let GenericDefId::TraitId(_) = *self {
+        // For traits and trait aliases the first type index is `Self`, skip it.
+        if let GenericDefId::TraitId(_) | GenericDefId::TraitAliasId(_) = *self {
             toc_idx_iter.next().unwrap(); // advance_by(1);
         }
 
@@ -21,6 +21,7 @@
 //!
 //! This is a work of fiction. Any similarities to Kotlin's `BindingContext` are
 //! a coincidence.
+pub mod def_to_src;
 pub mod keys;
 
 use std::{
@@ -75,11 +76,25 @@ impl<K: Hash + Eq + 'static, V: 'static> Policy for (K, V) {
 
 pub struct DynMap {
     pub(crate) map: Map,
+    /// Printers for the submaps of `map` that support [`DynMap::debug_dump`], registered by the
+    /// policy when the submap is created.
+    pub(crate) dumpers: Vec<fn(&DynMap, &mut String)>,
 }
 
 impl Default for DynMap {
     fn default() -> Self {
-        DynMap { map: Map::new() }
+        DynMap { map: Map::new(), dumpers: Vec::new() }
+    }
+}
+
+impl DynMap {
+    /// Renders the stored entries of every submap that supports it, for debugging.
+    pub fn debug_dump(&self) -> String {
+        let mut buf = String::new();
+        for dump in &self.dumpers {
+            dump(self, &mut buf);
+        }
+        buf
     }
 }
 
@@ -0,0 +1,123 @@
+//! keys to be used with `DynMap` for caching the sources of definitions, see
+//! [`crate::src::DefToSrcCache`]
+//!
+//! Keys with the same id and value types read and write the same submap, so every key here must
+//! have a type pair of its own. New keys should be added to the test at the bottom, which checks
+//! this.
+
+use either::Either;
+use hir_expand::InFile;
+use la_arena::ArenaMap;
+use syntax::{ast, AstPtr};
+use triomphe::Arc;
+
+use crate::{
+    body::BodySourceMap, ConstId, DefWithBodyId, EnumId, EnumVariantId, ExternCrateId, FieldId,
+    FunctionId, GenericDefId, ImplId, LocalFieldId, LocalLifetimeParamId, LocalTypeOrConstParamId,
+    Macro2Id, MacroRulesId, ProcMacroId, StaticId, StructId, TraitAliasId, TraitId, TypeAliasId,
+    UnionId, UseId, VariantId,
+};
+
+pub type Key<K, V> = crate::dyn_map::Key<K, InFile<AstPtr<V>>>;
+
+pub const FUNCTION: Key<FunctionId, ast::Fn> = Key::new();
+pub const CONST: Key<ConstId, ast::Const> = Key::new();
+pub const STATIC: Key<StaticId, ast::Static> = Key::new();
+pub const TYPE_ALIAS: Key<TypeAliasId, ast::TypeAlias> = Key::new();
+pub const IMPL: Key<ImplId, ast::Impl> = Key::new();
+pub const TRAIT: Key<TraitId, ast::Trait> = Key::new();
+pub const TRAIT_ALIAS: Key<TraitAliasId, ast::TraitAlias> = Key::new();
+pub const STRUCT: Key<StructId, ast::Struct> = Key::new();
+pub const UNION: Key<UnionId, ast::Union> = Key::new();
+pub const ENUM: Key<EnumId, ast::Enum> = Key::new();
+pub const ENUM_VARIANT: Key<EnumVariantId, ast::Variant> = Key::new();
+pub const EXTERN_CRATE: Key<ExternCrateId, ast::ExternCrate> = Key::new();
+pub const USE: Key<UseId, ast::Use> = Key::new();
+
+pub const MACRO_RULES: Key<MacroRulesId, ast::MacroRules> = Key::new();
+pub const MACRO2: Key<Macro2Id, ast::MacroDef> = Key::new();
+pub const PROC_MACRO: Key<ProcMacroId, ast::Fn> = Key::new();
+
+/// Body source maps, for looking up the sources of several definitions inside the same body.
+pub const BODY_SOURCE_MAP: crate::dyn_map::Key<DefWithBodyId, Arc<BodySourceMap>> =
+    crate::dyn_map::Key::new();
+
+/// The source pointer of a field.
+pub type FieldPtr = Either<AstPtr<ast::TupleField>, AstPtr<ast::RecordField>>;
+
+/// The source pointers of the fields of a variant.
+pub type FieldPtrs = Arc<ArenaMap<LocalFieldId, FieldPtr>>;
+
+/// Field pointers per variant, so that looking up the sources of several fields of a variant
+/// lowers its fields only once.
+pub const VARIANT_FIELDS: crate::dyn_map::Key<VariantId, InFile<FieldPtrs>> =
+    crate::dyn_map::Key::new();
+
+/// Field pointers per field, so that repeated lookups of a field don't go through its variant.
+pub const FIELD: crate::dyn_map::Key<FieldId, InFile<FieldPtr>> = crate::dyn_map::Key::new();
+
+/// The source pointers of the type and const parameters of a generic definition. For traits and
+/// trait aliases, the implicit `Self` parameter points to the trait itself.
+pub type TypeOrConstParamPtrs = Arc<
+    ArenaMap<
+        LocalTypeOrConstParamId,
+        Either<AstPtr<ast::TypeOrConstParam>, AstPtr<ast::TraitOrAlias>>,
+    >,
+>;
+
+/// Type and const parameter pointers per generic definition, so that looking up the sources of
+/// several parameters doesn't have to collect all of them again.
+pub const TYPE_OR_CONST_PARAMS: crate::dyn_map::Key<GenericDefId, InFile<TypeOrConstParamPtrs>> =
+    crate::dyn_map::Key::new();
+
+/// The source pointers of the lifetime parameters of a generic definition.
+pub type LifetimeParamPtrs = Arc<ArenaMap<LocalLifetimeParamId, AstPtr<ast::LifetimeParam>>>;
+
+/// Lifetime parameter pointers per generic definition, like [`TYPE_OR_CONST_PARAMS`].
+pub const LIFETIME_PARAMS: crate::dyn_map::Key<GenericDefId, InFile<LifetimeParamPtrs>> =
+    crate::dyn_map::Key::new();
+
+#[cfg(test)]
+mod tests {
+    use std::any::TypeId;
+
+    use rustc_hash::FxHashSet;
+
+    use super::*;
+
+    /// The submap a key reads and writes: keys with the same policy share it.
+    fn submap<K, V, P: 'static>(_: crate::dyn_map::Key<K, V, P>) -> TypeId {
+        TypeId::of::<P>()
+    }
+
+    #[test]
+    fn keys_have_distinct_submaps() {
+        let keys = [
+            ("FUNCTION", submap(FUNCTION)),
+            ("CONST", submap(CONST)),
+            ("STATIC", submap(STATIC)),
+            ("TYPE_ALIAS", submap(TYPE_ALIAS)),
+            ("IMPL", submap(IMPL)),
+            ("TRAIT", submap(TRAIT)),
+            ("TRAIT_ALIAS", submap(TRAIT_ALIAS)),
+            ("STRUCT", submap(STRUCT)),
+            ("UNION", submap(UNION)),
+            ("ENUM", submap(ENUM)),
+            ("ENUM_VARIANT", submap(ENUM_VARIANT)),
+            ("EXTERN_CRATE", submap(EXTERN_CRATE)),
+            ("USE", submap(USE)),
+            ("MACRO_RULES", submap(MACRO_RULES)),
+            ("MACRO2", submap(MACRO2)),
+            ("PROC_MACRO", submap(PROC_MACRO)),
+            ("BODY_SOURCE_MAP", submap(BODY_SOURCE_MAP)),
+            ("VARIANT_FIELDS", submap(VARIANT_FIELDS)),
+            ("FIELD", submap(FIELD)),
+            ("TYPE_OR_CONST_PARAMS", submap(TYPE_OR_CONST_PARAMS)),
+            ("LIFETIME_PARAMS", submap(LIFETIME_PARAMS)),
+        ];
+        let mut seen = FxHashSet::default();
+        for (name, submap) in keys {
+            assert!(seen.insert(submap), "`{name}` shares its submap with another key");
+        }
+    }
+}
@@ -1,6 +1,6 @@
 //! keys to be used with `DynMap`
 
-use std::marker::PhantomData;
+use std::{fmt, fmt::Write, marker::PhantomData};
 
 use hir_expand::{attrs::AttrId, MacroCallId};
 use rustc_hash::FxHashMap;
@@ -8,8 +8,8 @@ use syntax::{ast, AstNode, AstPtr};
 
 use crate::{
     dyn_map::{DynMap, Policy},
-    BlockId, ConstId, EnumId, EnumVariantId, ExternCrateId, FieldId, FunctionId, ImplId,
-    LifetimeParamId, Macro2Id, MacroRulesId, ProcMacroId, StaticId, StructId, TraitAliasId,
+    BlockId, ConstId, EnumId, EnumVariantId, ExternBlockId, ExternCrateId, FieldId, FunctionId,
+    ImplId, LifetimeParamId, Macro2Id, MacroRulesId, ProcMacroId, StaticId, StructId, TraitAliasId,
     TraitId, TypeAliasId, TypeOrConstParamId, UnionId, UseId,
 };
 
@@ -28,6 +28,7 @@ pub const UNION: Key<ast::Union, UnionId> = Key::new();
 pub const ENUM: Key<ast::Enum, EnumId> = Key::new();
 pub const EXTERN_CRATE: Key<ast::ExternCrate, ExternCrateId> = Key::new();
 pub const USE: Key<ast::Use, UseId> = Key::new();
+pub const EXTERN_BLOCK: Key<ast::ExternBlock, ExternBlockId> = Key::new();
 
 pub const ENUM_VARIANT: Key<ast::Variant, EnumVariantId> = Key::new();
 pub const TUPLE_FIELD: Key<ast::TupleField, FieldId> = Key::new();
@@ -54,10 +55,25 @@ pub struct AstPtrPolicy<AST, ID> {
     _phantom: PhantomData<(AST, ID)>,
 }
 
-impl<AST: AstNode + 'static, ID: 'static> Policy for AstPtrPolicy<AST, ID> {
+impl<AST: AstNode + 'static, ID: fmt::Debug + 'static> AstPtrPolicy<AST, ID> {
+    fn dump(map: &DynMap, buf: &mut String) {
+        let Some(entries) = map.map.get::<FxHashMap<AstPtr<AST>, ID>>() else { return };
+        let mut entries: Vec<_> = entries.iter().map(|(ptr, id)| (ptr.text_range(), id)).collect();
+        entries.sort_by_key(|(range, _)| (range.start(), range.end()));
+        let _ = writeln!(buf, "{}:", std::any::type_name::<AST>());
+        for (range, id) in entries {
+            let _ = writeln!(buf, "    {range:?} => {id:?}");
+        }
+    }
+}
+
+impl<AST: AstNode + 'static, ID: fmt::Debug + 'static> Policy for AstPtrPolicy<AST, ID> {
     type K = AstPtr<AST>;
     type V = ID;
     fn insert(map: &mut DynMap, key: AstPtr<AST>, value: ID) {
+        if map.map.get::<FxHashMap<AstPtr<AST>, ID>>().is_none() {
+            map.dumpers.push(Self::dump);
+        }
         map.map
             .entry::<FxHashMap<AstPtr<AST>, ID>>()
             .or_insert_with(Default::default)
@@ -18,8 +18,8 @@ use crate::{
     db::DefDatabase,
     per_ns::PerNs,
     visibility::{Visibility, VisibilityExplicitness},
-    AdtId, BuiltinType, ConstId, ExternCrateId, HasModule, ImplId, LocalModuleId, Lookup, MacroId,
-    ModuleDefId, ModuleId, TraitId, UseId,
+    AdtId, BuiltinType, ConstId, ExternBlockId, ExternCrateId, HasModule, ImplId, LocalModuleId,
+    Lookup, MacroId, ModuleDefId, ModuleId, TraitId, UseId,
 };
 
 #[derive(Debug, Default)]
@@ -91,6 +91,7 @@ pub struct ItemScope {
 
     use_decls: Vec<UseId>,
     extern_crate_decls: Vec<ExternCrateId>,
+    extern_blocks: Vec<ExternBlockId>,
     /// Macros visible in current module in legacy textual scope
     ///
     /// For macros invoked by an unqualified identifier like `bar!()`, `legacy_macros` will be searched in first.
@@ -230,6 +231,10 @@ impl ItemScope {
         self.use_decls.iter().copied()
     }
 
+    pub fn extern_blocks(&self) -> impl ExactSizeIterator<Item = ExternBlockId> + '_ {
+        self.extern_blocks.iter().copied()
+    }
+
     pub fn impls(&self) -> impl ExactSizeIterator<Item = ImplId> + '_ {
         self.impls.iter().copied()
     }
@@ -375,6 +380,10 @@ impl ItemScope {
         self.extern_crate_decls.push(extern_crate);
     }
 
+    pub(crate) fn define_extern_block(&mut self, extern_block: ExternBlockId) {
+        self.extern_blocks.push(extern_block);
+    }
+
     pub(crate) fn define_unnamed_const(&mut self, konst: ConstId) {
         self.unnamed_consts.push(konst);
     }
@@ -735,6 +744,7 @@ impl ItemScope {
             attr_macros,
             derive_macros,
             extern_crate_decls,
+            extern_blocks,
             use_decls,
             use_imports_values,
             use_imports_types,
@@ -756,6 +766,7 @@ impl ItemScope {
         attr_macros.shrink_to_fit();
         derive_macros.shrink_to_fit();
         extern_crate_decls.shrink_to_fit();
+        extern_blocks.shrink_to_fit();
         use_decls.shrink_to_fit();
         macro_invocations.shrink_to_fit();
     }
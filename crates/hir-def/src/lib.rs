@@ -940,6 +940,22 @@ impl_from!(
 );
 
 impl GenericDefId {
+    /// Returns the file the definition, and so all of its generic parameters, is declared in.
+    pub fn file_id(self, db: &dyn DefDatabase) -> HirFileId {
+        match self {
+            GenericDefId::FunctionId(it) => it.lookup(db).id.file_id(),
+            GenericDefId::TypeAliasId(it) => it.lookup(db).id.file_id(),
+            GenericDefId::AdtId(AdtId::StructId(it)) => it.lookup(db).id.file_id(),
+            GenericDefId::AdtId(AdtId::UnionId(it)) => it.lookup(db).id.file_id(),
+            GenericDefId::AdtId(AdtId::EnumId(it)) => it.lookup(db).id.file_id(),
+            GenericDefId::TraitId(it) => it.lookup(db).id.file_id(),
+            GenericDefId::TraitAliasId(it) => it.lookup(db).id.file_id(),
+            GenericDefId::ImplId(it) => it.lookup(db).id.file_id(),
+            GenericDefId::ConstId(it) => it.lookup(db).id.file_id(),
+            GenericDefId::EnumVariantId(it) => it.lookup(db).id.file_id(),
+        }
+    }
+
     fn file_id_and_params_of(
         self,
         db: &dyn DefDatabase,
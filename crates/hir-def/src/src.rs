@@ -1,13 +1,23 @@
 //! Utilities for mapping between hir IDs and the surface syntax.
 
+use std::{cell::RefCell, hash::Hash};
+
 use either::Either;
-use hir_expand::InFile;
-use la_arena::ArenaMap;
-use syntax::{ast, AstNode, AstPtr};
+use hir_expand::{HirFileId, InFile};
+use la_arena::{Arena, ArenaMap};
+use rustc_hash::FxHashMap;
+use syntax::{ast, AstNode, AstPtr, SyntaxNode, TextRange};
+use triomphe::Arc;
 
 use crate::{
-    data::adt::lower_struct, db::DefDatabase, item_tree::ItemTreeNode, trace::Trace, GenericDefId,
-    ItemTreeLoc, LocalFieldId, LocalLifetimeParamId, LocalTypeOrConstParamId, Lookup, UseId,
+    data::adt::lower_struct,
+    db::DefDatabase,
+    dyn_map::{def_to_src, DynMap, Key, Policy},
+    hir::LabelId,
+    item_tree::ItemTreeNode,
+    trace::Trace,
+    DefWithBodyId, ExternBlockId, FieldId, GenericDefId, ItemTreeLoc, LifetimeParamId,
+    LocalFieldId, LocalLifetimeParamId, LocalTypeOrConstParamId, Lookup, TypeOrConstParamId, UseId,
     VariantId,
 };
 
@@ -18,6 +28,10 @@ pub trait HasSource {
         InFile::new(file_id, value.to_node(&db.parse_or_expand(file_id)))
     }
     fn ast_ptr(&self, db: &dyn DefDatabase) -> InFile<AstPtr<Self::Value>>;
+    /// Returns the range of the source node, without parsing the file to materialize it.
+    fn source_range(&self, db: &dyn DefDatabase) -> InFile<TextRange> {
+        self.ast_ptr(db).map(|it| it.text_range())
+    }
 }
 
 impl<T> HasSource for T
@@ -37,6 +51,312 @@ where
     }
 }
 
+/// A cache for the source pointers of definitions, keyed by the keys in
+/// [`crate::dyn_map::def_to_src`].
+pub trait SrcDefCacheContext {
+    fn get<K, V: Clone, P: Policy<K = K, V = V>>(
+        &self,
+        map_key: Key<K, V, P>,
+        key: &K,
+    ) -> Option<V>;
+    fn insert<K, V, P: Policy<K = K, V = V>>(&self, map_key: Key<K, V, P>, key: K, value: V);
+    /// Returns whether a value is cached for `key`, without cloning it like [`Self::get`] does.
+    fn contains<K, V, P: Policy<K = K, V = V>>(&self, map_key: Key<K, V, P>, key: &K) -> bool;
+    /// Returns the syntax tree of `file_id`, parsing or expanding it only once per context.
+    fn parse_or_expand(&self, db: &dyn DefDatabase, file_id: HirFileId) -> SyntaxNode;
+}
+
+/// The default [`SrcDefCacheContext`], backed by a [`DynMap`].
+#[derive(Default)]
+pub struct DefToSrcCache {
+    map: RefCell<DynMap>,
+    /// The roots handed out so far. Nodes resolved against them keep the tree alive on their
+    /// own, so dropping the cache never invalidates a previously returned `InFile`.
+    parsed: RefCell<FxHashMap<HirFileId, SyntaxNode>>,
+}
+
+impl SrcDefCacheContext for DefToSrcCache {
+    fn get<K, V: Clone, P: Policy<K = K, V = V>>(
+        &self,
+        map_key: Key<K, V, P>,
+        key: &K,
+    ) -> Option<V> {
+        self.map.borrow()[map_key].get(key).cloned()
+    }
+    fn insert<K, V, P: Policy<K = K, V = V>>(&self, map_key: Key<K, V, P>, key: K, value: V) {
+        self.map.borrow_mut()[map_key].insert(key, value)
+    }
+    fn contains<K, V, P: Policy<K = K, V = V>>(&self, map_key: Key<K, V, P>, key: &K) -> bool {
+        self.map.borrow()[map_key].get(key).is_some()
+    }
+    fn parse_or_expand(&self, db: &dyn DefDatabase, file_id: HirFileId) -> SyntaxNode {
+        self.parsed
+            .borrow_mut()
+            .entry(file_id)
+            .or_insert_with(|| db.parse_or_expand(file_id))
+            .clone()
+    }
+}
+
+impl DefToSrcCache {
+    /// Makes later lookups in `file_id` resolve against `root`, e.g. a macro expansion that was
+    /// already parsed elsewhere. Nodes handed out for a previous root of the file stay valid.
+    pub fn insert_parsed(&self, file_id: HirFileId, root: SyntaxNode) {
+        self.parsed.borrow_mut().insert(file_id, root);
+    }
+
+    /// Drops the tree cached for `file_id`, so that the next lookup parses or expands it again.
+    /// The cached pointers aren't tracked per file and might point into the old tree, so all of
+    /// them are dropped as well.
+    pub fn invalidate_file(&self, file_id: HirFileId) {
+        self.parsed.borrow_mut().remove(&file_id);
+        *self.map.borrow_mut() = DynMap::default();
+    }
+
+    /// Drops all cached trees and pointers.
+    pub fn clear(&self) {
+        self.parsed.borrow_mut().clear();
+        *self.map.borrow_mut() = DynMap::default();
+    }
+
+    /// Drops the value cached for `def` under `map_key`, e.g. after only that definition was
+    /// renamed. Everything else stays cached.
+    pub fn invalidate_def<K: Hash + Eq + 'static, V: 'static>(
+        &self,
+        _map_key: crate::dyn_map::Key<K, V>,
+        def: &K,
+    ) {
+        if let Some(map) = self.map.borrow_mut().map.get_mut::<FxHashMap<K, V>>() {
+            map.remove(def);
+        }
+    }
+}
+
+/// A reference to a [`SrcDefCacheContext`] that lookups should go through, if there is one.
+pub enum CacheRef<'a, Ctx> {
+    Cached(&'a Ctx),
+    Uncached,
+}
+
+impl<Ctx> Clone for CacheRef<'_, Ctx> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Ctx> Copy for CacheRef<'_, Ctx> {}
+
+impl<'a, Ctx> From<&'a Ctx> for CacheRef<'a, Ctx> {
+    fn from(ctx: &'a Ctx) -> Self {
+        CacheRef::Cached(ctx)
+    }
+}
+
+impl<'a, Ctx> From<Option<&'a Ctx>> for CacheRef<'a, Ctx> {
+    fn from(ctx: Option<&'a Ctx>) -> Self {
+        ctx.map_or(CacheRef::Uncached, CacheRef::Cached)
+    }
+}
+
+impl<Ctx: SrcDefCacheContext> CacheRef<'_, Ctx> {
+    /// Returns the value cached for `key`, computing it with `f` and caching it on a miss.
+    /// Without a cache, this always calls `f`.
+    pub fn get_or_insert_with<K, V: Clone, P: Policy<K = K, V = V>>(
+        self,
+        map_key: Key<K, V, P>,
+        key: K,
+        f: impl FnOnce() -> V,
+    ) -> V {
+        match self {
+            CacheRef::Cached(ctx) => {
+                if let Some(value) = ctx.get(map_key, &key) {
+                    return value;
+                }
+                let value = f();
+                ctx.insert(map_key, key, value.clone());
+                value
+            }
+            CacheRef::Uncached => f(),
+        }
+    }
+
+    /// Returns the syntax tree of `file_id`, going through the cache if there is one.
+    pub fn parse_or_expand(self, db: &dyn DefDatabase, file_id: HirFileId) -> SyntaxNode {
+        match self {
+            CacheRef::Cached(ctx) => ctx.parse_or_expand(db, file_id),
+            CacheRef::Uncached => db.parse_or_expand(file_id),
+        }
+    }
+}
+
+/// Returns the source pointer of `id`, going through `ctx` if it is cached.
+pub fn ast_ptr_by_key<ID, Loc, Ctx>(
+    db: &dyn DefDatabase,
+    ctx: CacheRef<'_, Ctx>,
+    key: def_to_src::Key<ID, Loc::Value>,
+    id: ID,
+) -> InFile<AstPtr<Loc::Value>>
+where
+    ID: for<'db> Lookup<Database<'db> = dyn DefDatabase + 'db, Data = Loc>
+        + Copy
+        + Hash
+        + Eq
+        + 'static,
+    Loc: HasSource,
+    Loc::Value: 'static,
+    Ctx: SrcDefCacheContext,
+{
+    ctx.get_or_insert_with(key, id, || id.lookup(db).ast_ptr(db))
+}
+
+/// Resolves `ptr` against `root`.
+///
+/// A pointer served from a cache that missed an edit may no longer point into `root`. With debug
+/// assertions, such a pointer is reported and yields `None` instead of panicking or silently
+/// resolving to an unrelated node.
+pub fn ptr_to_node<N: AstNode>(root: &SyntaxNode, ptr: &AstPtr<N>) -> Option<N> {
+    #[cfg(debug_assertions)]
+    {
+        let node = ptr.syntax_node_ptr().try_to_node(root).and_then(N::cast);
+        if node.is_none() {
+            tracing::error!(
+                "stale source pointer {:?} for tree with range {:?}",
+                ptr,
+                root.text_range()
+            );
+        }
+        node
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        Some(ptr.to_node(root))
+    }
+}
+
+/// Like [`HasSource::source`], but looks up the source pointer of `id` through `ctx`.
+pub fn source_with_ctx<ID, Loc, Ctx>(
+    db: &dyn DefDatabase,
+    ctx: CacheRef<'_, Ctx>,
+    key: def_to_src::Key<ID, Loc::Value>,
+    id: ID,
+) -> Option<InFile<Loc::Value>>
+where
+    ID: for<'db> Lookup<Database<'db> = dyn DefDatabase + 'db, Data = Loc>
+        + Copy
+        + Hash
+        + Eq
+        + 'static,
+    Loc: HasSource,
+    Loc::Value: 'static,
+    Ctx: SrcDefCacheContext,
+{
+    let InFile { file_id, value } = ast_ptr_by_key(db, ctx, key, id);
+    let node = ptr_to_node(&ctx.parse_or_expand(db, file_id), &value)?;
+    Some(InFile::new(file_id, node))
+}
+
+/// Like [`HasSource::source_range`], but looks up the source pointer of `id` through `ctx`. The
+/// file is never parsed, making this a cheap way to get at a definition's range.
+pub fn source_range_with_ctx<ID, Loc, Ctx>(
+    db: &dyn DefDatabase,
+    ctx: CacheRef<'_, Ctx>,
+    key: def_to_src::Key<ID, Loc::Value>,
+    id: ID,
+) -> InFile<TextRange>
+where
+    ID: for<'db> Lookup<Database<'db> = dyn DefDatabase + 'db, Data = Loc>
+        + Copy
+        + Hash
+        + Eq
+        + 'static,
+    Loc: HasSource,
+    Loc::Value: 'static,
+    Ctx: SrcDefCacheContext,
+{
+    ast_ptr_by_key(db, ctx, key, id).map(|it| it.text_range())
+}
+
+/// Returns the source pointer of `field`. With a cache, the fields of its variant are lowered only
+/// once, no matter how many of them are looked up, and each field's pointer is memoized.
+pub fn field_ptr<Ctx: SrcDefCacheContext>(
+    db: &dyn DefDatabase,
+    ctx: CacheRef<'_, Ctx>,
+    field: FieldId,
+) -> Option<InFile<def_to_src::FieldPtr>> {
+    if let CacheRef::Cached(cache) = ctx {
+        if let Some(ptr) = cache.get(def_to_src::FIELD, &field) {
+            return Some(ptr);
+        }
+    }
+    let ptrs = ctx.get_or_insert_with(def_to_src::VARIANT_FIELDS, field.parent, || {
+        field.parent.child_source(db).map(|fields| {
+            Arc::new(
+                fields
+                    .iter()
+                    .map(|(idx, it)| (idx, it.as_ref().map_either(AstPtr::new, AstPtr::new)))
+                    .collect(),
+            )
+        })
+    });
+    let ptr = ptrs.with_value(ptrs.value.get(field.local_id)?.clone());
+    if let CacheRef::Cached(cache) = ctx {
+        cache.insert(def_to_src::FIELD, field, ptr.clone());
+    }
+    Some(ptr)
+}
+
+/// Returns the source pointer of the type or const parameter `param`. With a cache, the
+/// parameters of its parent are collected only once, no matter how many of them are looked up.
+pub fn type_or_const_param_ptr<Ctx: SrcDefCacheContext>(
+    db: &dyn DefDatabase,
+    ctx: CacheRef<'_, Ctx>,
+    param: TypeOrConstParamId,
+) -> Option<InFile<Either<AstPtr<ast::TypeOrConstParam>, AstPtr<ast::TraitOrAlias>>>> {
+    let ptrs = ctx.get_or_insert_with(def_to_src::TYPE_OR_CONST_PARAMS, param.parent, || {
+        type_or_const_param_sources(db, ctx, param.parent).map(|params| {
+            Arc::new(
+                params
+                    .iter()
+                    .map(|(idx, it)| (idx, it.as_ref().map_either(AstPtr::new, AstPtr::new)))
+                    .collect(),
+            )
+        })
+    });
+    let ptr = ptrs.value.get(param.local_id)?.clone();
+    Some(ptrs.with_value(ptr))
+}
+
+/// Returns the source pointer of the lifetime parameter `param`, collecting the lifetime
+/// parameters of its parent only once with a cache.
+pub fn lifetime_param_ptr<Ctx: SrcDefCacheContext>(
+    db: &dyn DefDatabase,
+    ctx: CacheRef<'_, Ctx>,
+    param: LifetimeParamId,
+) -> Option<InFile<AstPtr<ast::LifetimeParam>>> {
+    let ptrs = ctx.get_or_insert_with(def_to_src::LIFETIME_PARAMS, param.parent, || {
+        param
+            .parent
+            .child_source(db)
+            .map(|params| Arc::new(params.iter().map(|(idx, it)| (idx, AstPtr::new(it))).collect()))
+    });
+    let ptr = ptrs.value.get(param.local_id)?.clone();
+    Some(ptrs.with_value(ptr))
+}
+
+/// Returns the source pointer of `label` in the body of `owner`. With a cache, the body source
+/// map is fetched only once, no matter how many labels of the body are looked up.
+pub fn label_ptr<Ctx: SrcDefCacheContext>(
+    db: &dyn DefDatabase,
+    ctx: CacheRef<'_, Ctx>,
+    owner: DefWithBodyId,
+    label: LabelId,
+) -> InFile<AstPtr<ast::Label>> {
+    let source_map = ctx.get_or_insert_with(def_to_src::BODY_SOURCE_MAP, owner, || {
+        db.body_with_source_map(owner).1
+    });
+    source_map.label_syntax(label)
+}
+
 pub trait HasChildSource<ChildId> {
     type Value;
     fn child_source(&self, db: &dyn DefDatabase) -> InFile<ArenaMap<ChildId, Self::Value>>;
@@ -57,43 +377,79 @@ impl HasChildSource<la_arena::Idx<ast::UseTree>> for UseId {
     }
 }
 
+impl HasChildSource<la_arena::Idx<ast::ExternItem>> for ExternBlockId {
+    type Value = ast::ExternItem;
+    fn child_source(
+        &self,
+        db: &dyn DefDatabase,
+    ) -> InFile<ArenaMap<la_arena::Idx<ast::ExternItem>, Self::Value>> {
+        let loc = &self.lookup(db);
+        let file_id = loc.id.file_id();
+        let item_tree = loc.id.item_tree(db);
+        let ast_id_map = db.ast_id_map(file_id);
+        let root = db.parse_or_expand(file_id);
+
+        let mut items = Arena::new();
+        for item in item_tree[loc.id.value].children.iter() {
+            let node = ast_id_map.get(item.ast_id(&item_tree)).to_node(&root);
+            // Only foreign items are lowered into an extern block's children.
+            if let Some(item) = ast::ExternItem::cast(node.syntax().clone()) {
+                items.alloc(item);
+            }
+        }
+        InFile::new(file_id, items.into_iter().collect())
+    }
+}
+
 impl HasChildSource<LocalTypeOrConstParamId> for GenericDefId {
     type Value = Either<ast::TypeOrConstParam, ast::TraitOrAlias>;
     fn child_source(
         &self,
         db: &dyn DefDatabase,
     ) -> InFile<ArenaMap<LocalTypeOrConstParamId, Self::Value>> {
-        let generic_params = db.generic_params(*self);
-        let mut idx_iter = generic_params.type_or_consts.iter().map(|(idx, _)| idx);
+        type_or_const_param_sources(db, CacheRef::<DefToSrcCache>::Uncached, *self)
+    }
+}
 
-        let (file_id, generic_params_list) = self.file_id_and_params_of(db);
+/// The sources of the type and const parameters of `def`. With a cache, the source of a trait or
+/// trait alias, which its implicit `Self` parameter points to, is looked up through it.
+fn type_or_const_param_sources<Ctx: SrcDefCacheContext>(
+    db: &dyn DefDatabase,
+    ctx: CacheRef<'_, Ctx>,
+    def: GenericDefId,
+) -> InFile<ArenaMap<LocalTypeOrConstParamId, Either<ast::TypeOrConstParam, ast::TraitOrAlias>>> {
+    let generic_params = db.generic_params(def);
+    let mut idx_iter = generic_params.type_or_consts.iter().map(|(idx, _)| idx);
 
-        let mut params = ArenaMap::default();
+    let (file_id, generic_params_list) = def.file_id_and_params_of(db);
 
-        // For traits and trait aliases the first type index is `Self`, we need to add it before
-        // the other params.
-        match *self {
-            GenericDefId::TraitId(id) => {
-                let trait_ref = id.lookup(db).source(db).value;
-                let idx = idx_iter.next().unwrap();
-                params.insert(idx, Either::Right(ast::TraitOrAlias::Trait(trait_ref)));
-            }
-            GenericDefId::TraitAliasId(id) => {
-                let alias = id.lookup(db).source(db).value;
-                let idx = idx_iter.next().unwrap();
-                params.insert(idx, Either::Right(ast::TraitOrAlias::TraitAlias(alias)));
+    let mut params = ArenaMap::default();
+
+    // For traits and trait aliases the first type index is `Self`, we need to add it before
+    // the other params.
+    match def {
+        GenericDefId::TraitId(id) => {
+            let idx = idx_iter.next().unwrap();
+            if let Some(src) = source_with_ctx(db, ctx, def_to_src::TRAIT, id) {
+                params.insert(idx, Either::Right(ast::TraitOrAlias::Trait(src.value)));
             }
-            _ => {}
         }
-
-        if let Some(generic_params_list) = generic_params_list {
-            for (idx, ast_param) in idx_iter.zip(generic_params_list.type_or_const_params()) {
-                params.insert(idx, Either::Left(ast_param));
+        GenericDefId::TraitAliasId(id) => {
+            let idx = idx_iter.next().unwrap();
+            if let Some(src) = source_with_ctx(db, ctx, def_to_src::TRAIT_ALIAS, id) {
+                params.insert(idx, Either::Right(ast::TraitOrAlias::TraitAlias(src.value)));
             }
         }
+        _ => {}
+    }
 
-        InFile::new(file_id, params)
+    if let Some(generic_params_list) = generic_params_list {
+        for (idx, ast_param) in idx_iter.zip(generic_params_list.type_or_const_params()) {
+            params.insert(idx, Either::Left(ast_param));
+        }
     }
+
+    InFile::new(file_id, params)
 }
 
 impl HasChildSource<LocalLifetimeParamId> for GenericDefId {
@@ -158,3 +514,528 @@ impl HasChildSource<LocalFieldId> for VariantId {
         src.with_value(trace.into_map())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use base_db::SourceDatabaseExt2 as _;
+    use itertools::Itertools;
+    use la_arena::RawIdx;
+    use syntax::ast::HasName;
+    use test_fixture::WithFixture;
+
+    use crate::{test_db::TestDB, AdtId, ModuleDefId, StructId};
+
+    use super::*;
+
+    fn structs_of_file(ra_fixture: &str) -> (TestDB, base_db::FileId, Vec<StructId>) {
+        let (db, file_id) = TestDB::with_single_file(ra_fixture);
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let structs = def_map[module.local_id]
+            .scope
+            .declarations()
+            .filter_map(|def| match def {
+                ModuleDefId::AdtId(AdtId::StructId(it)) => Some(it),
+                _ => None,
+            })
+            .collect();
+        (db, file_id, structs)
+    }
+
+    #[test]
+    fn source_with_ctx_parses_file_once() {
+        let (db, file_id, structs) = structs_of_file(
+            r#"
+struct A;
+struct B(u32);
+struct C { c: u8 }
+fn f() {}
+struct D;
+"#,
+        );
+        assert_eq!(structs.len(), 4);
+
+        let cache = DefToSrcCache::default();
+        let sources: Vec<InFile<ast::Struct>> = structs
+            .iter()
+            .map(|&id| {
+                source_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, id).unwrap()
+            })
+            .collect();
+
+        assert_eq!(cache.parsed.borrow().len(), 1);
+        let root = &cache.parsed.borrow()[&HirFileId::from(file_id)];
+        for src in &sources {
+            assert_eq!(src.file_id, HirFileId::from(file_id));
+            assert_eq!(&src.value.syntax().ancestors().last().unwrap(), root);
+        }
+    }
+
+    #[test]
+    fn source_with_ctx_shares_parse_across_def_kinds() {
+        let (db, file_id) = TestDB::with_single_file(
+            r#"
+struct S;
+fn f() {}
+"#,
+        );
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let scope = &def_map[module.local_id].scope;
+        let (strukt, func) =
+            scope.declarations().fold((None, None), |(strukt, func), def| match def {
+                ModuleDefId::AdtId(AdtId::StructId(it)) => (Some(it), func),
+                ModuleDefId::FunctionId(it) => (strukt, Some(it)),
+                _ => (strukt, func),
+            });
+
+        let cache = DefToSrcCache::default();
+        let ctx = CacheRef::Cached(&cache);
+        let strukt = source_with_ctx(&db, ctx, def_to_src::STRUCT, strukt.unwrap()).unwrap();
+        let ctx = CacheRef::Cached(&cache);
+        let func = source_with_ctx(&db, ctx, def_to_src::FUNCTION, func.unwrap()).unwrap();
+
+        assert_eq!(cache.parsed.borrow().len(), 1);
+        assert_eq!(
+            strukt.value.syntax().ancestors().last(),
+            func.value.syntax().ancestors().last()
+        );
+    }
+
+    #[test]
+    fn invalidate_file_drops_the_old_tree() {
+        let (mut db, file_id, structs) = structs_of_file("struct A;");
+        let cache = DefToSrcCache::default();
+        let src = source_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, structs[0]);
+        assert_eq!(src.unwrap().value.to_string(), "struct A;");
+
+        db.set_file_text(file_id, "struct A {}");
+        cache.invalidate_file(file_id.into());
+        assert!(cache.parsed.borrow().is_empty());
+        assert!(!cache.contains(def_to_src::STRUCT, &structs[0]));
+
+        let src = source_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, structs[0]);
+        assert_eq!(src.unwrap().value.to_string(), "struct A {}");
+    }
+
+    #[test]
+    fn clear_drops_everything() {
+        let (db, file_id, structs) = structs_of_file("struct A;\nstruct B;");
+        let cache = DefToSrcCache::default();
+        for &id in &structs {
+            source_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, id).unwrap();
+        }
+        assert!(cache.parsed.borrow().contains_key(&HirFileId::from(file_id)));
+
+        cache.clear();
+        assert!(cache.parsed.borrow().is_empty());
+        assert!(structs.iter().all(|it| !cache.contains(def_to_src::STRUCT, it)));
+    }
+
+    #[test]
+    fn source_range_matches_node_range() {
+        let (db, file_id) = TestDB::with_single_file(
+            r#"
+struct S { f: u32 }
+fn f() {}
+enum E { A, B }
+"#,
+        );
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let cache = DefToSrcCache::default();
+        let mut seen = 0;
+        for def in def_map[module.local_id].scope.declarations() {
+            let (range, node) = match def {
+                ModuleDefId::AdtId(AdtId::StructId(it)) => (
+                    source_range_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, it),
+                    it.lookup(&db).source(&db).map(|it| it.syntax().text_range()),
+                ),
+                ModuleDefId::AdtId(AdtId::EnumId(it)) => (
+                    source_range_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::ENUM, it),
+                    it.lookup(&db).source(&db).map(|it| it.syntax().text_range()),
+                ),
+                ModuleDefId::FunctionId(it) => (
+                    source_range_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::FUNCTION, it),
+                    it.lookup(&db).source(&db).map(|it| it.syntax().text_range()),
+                ),
+                _ => continue,
+            };
+            assert_eq!(range, node);
+            seen += 1;
+        }
+        assert_eq!(seen, 3);
+        // Only the pointers were looked up, the file was never parsed through the cache.
+        assert!(cache.parsed.borrow().is_empty());
+    }
+
+    /// Looks up the sources of `a` and `b`, then swaps their cached pointers. Lookups served from
+    /// the cache come back swapped.
+    fn check_served_from_cache<ID, Loc>(
+        db: &TestDB,
+        key: def_to_src::Key<ID, Loc::Value>,
+        a: ID,
+        b: ID,
+    ) where
+        ID: for<'db> Lookup<Database<'db> = dyn DefDatabase + 'db, Data = Loc>
+            + Copy
+            + Hash
+            + Eq
+            + 'static,
+        Loc: HasSource,
+        Loc::Value: 'static,
+    {
+        let cache = DefToSrcCache::default();
+        let ctx = CacheRef::Cached(&cache);
+        let text = |id| source_with_ctx(db, ctx, key, id).unwrap().value.syntax().to_string();
+        let (text_a, text_b) = (text(a), text(b));
+        assert_ne!(text_a, text_b);
+
+        let (ptr_a, ptr_b) = (cache.get(key, &a).unwrap(), cache.get(key, &b).unwrap());
+        cache.insert(key, a, ptr_b);
+        cache.insert(key, b, ptr_a);
+        assert_eq!((text(a), text(b)), (text_b, text_a));
+    }
+
+    #[test]
+    fn adt_sources_are_served_from_the_cache() {
+        let (db, file_id) = TestDB::with_single_file(
+            r#"
+struct A;
+struct B;
+union U { u: u8 }
+union V { v: u8 }
+enum E {}
+enum F {}
+"#,
+        );
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let (mut structs, mut unions, mut enums) = (Vec::new(), Vec::new(), Vec::new());
+        for def in def_map[module.local_id].scope.declarations() {
+            match def {
+                ModuleDefId::AdtId(AdtId::StructId(it)) => structs.push(it),
+                ModuleDefId::AdtId(AdtId::UnionId(it)) => unions.push(it),
+                ModuleDefId::AdtId(AdtId::EnumId(it)) => enums.push(it),
+                _ => (),
+            }
+        }
+
+        check_served_from_cache(&db, def_to_src::STRUCT, structs[0], structs[1]);
+        check_served_from_cache(&db, def_to_src::UNION, unions[0], unions[1]);
+        check_served_from_cache(&db, def_to_src::ENUM, enums[0], enums[1]);
+    }
+
+    #[test]
+    fn extern_block_child_sources() {
+        let (db, file_id) = TestDB::with_single_file(
+            r#"
+extern "C" {
+    fn f();
+    static S: u8;
+}
+"#,
+        );
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let block = def_map[module.local_id]
+            .scope
+            .declarations()
+            .find_map(|def| match def {
+                ModuleDefId::FunctionId(it) => match it.lookup(&db).container {
+                    crate::ItemContainerId::ExternBlockId(it) => Some(it),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .unwrap();
+
+        let items = block.child_source(&db);
+        assert_eq!(items.file_id, HirFileId::from(file_id));
+        let items: Vec<_> = items.value.values().map(|it| it.syntax().to_string()).collect();
+        assert_eq!(items, ["fn f();", "static S: u8;"]);
+    }
+
+    #[test]
+    fn cached_and_uncached_lookups() {
+        let (db, _, structs) = structs_of_file("struct A;\nstruct B;");
+        let cache = DefToSrcCache::default();
+        let cached = CacheRef::from(Some(&cache));
+        let uncached = CacheRef::<DefToSrcCache>::from(None);
+        assert!(matches!(uncached, CacheRef::Uncached));
+
+        // Only the cached lookup remembers the value, the uncached one computes it every time.
+        let mut calls = 0;
+        for _ in 0..2 {
+            cached.get_or_insert_with(def_to_src::STRUCT, structs[0], || {
+                calls += 1;
+                structs[0].lookup(&db).ast_ptr(&db)
+            });
+        }
+        assert_eq!(calls, 1);
+        for _ in 0..2 {
+            uncached.get_or_insert_with(def_to_src::STRUCT, structs[1], || {
+                calls += 1;
+                structs[1].lookup(&db).ast_ptr(&db)
+            });
+        }
+        assert_eq!(calls, 3);
+        assert!(!cache.contains(def_to_src::STRUCT, &structs[1]));
+
+        // Both resolve to the same sources, but only the cached one keeps the parsed tree.
+        for &id in &structs {
+            let text = |ctx| source_with_ctx(&db, ctx, def_to_src::STRUCT, id).unwrap().value;
+            assert_eq!(text(cached).to_string(), text(uncached).to_string());
+        }
+        assert_eq!(cache.parsed.borrow().len(), 1);
+    }
+
+    #[test]
+    fn contains_reports_cached_entries() {
+        let (db, _, structs) = structs_of_file("struct A;");
+        let cache = DefToSrcCache::default();
+        assert!(!cache.contains(def_to_src::STRUCT, &structs[0]));
+
+        ast_ptr_by_key(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, structs[0]);
+        assert!(cache.contains(def_to_src::STRUCT, &structs[0]));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn source_with_ctx_rejects_stale_ptr() {
+        let (db, file_id, structs) = structs_of_file("struct A;");
+
+        // A pointer taken from a longer version of the file, as a cache that missed an edit
+        // would hand out.
+        let stale = ast::SourceFile::parse("struct A;\nstruct B;", span::Edition::CURRENT).tree();
+        let stale = AstPtr::new(
+            &stale.syntax().descendants().filter_map(ast::Struct::cast).last().unwrap(),
+        );
+
+        let cache = DefToSrcCache::default();
+        cache.insert(def_to_src::STRUCT, structs[0], InFile::new(file_id.into(), stale));
+        let src = source_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, structs[0]);
+        assert!(src.is_none());
+    }
+
+    #[test]
+    fn field_ptr_lowers_variant_fields_once() {
+        let fields = (0..50).map(|i| format!("f{i}: u32")).join(", ");
+        let (db, _, structs) = structs_of_file(&format!("struct S {{ {fields} }}"));
+        let parent = VariantId::StructId(structs[0]);
+
+        let cache = DefToSrcCache::default();
+        for i in 0..50 {
+            let local_id = LocalFieldId::from_raw(RawIdx::from(i));
+            let ptr = field_ptr(&db, CacheRef::Cached(&cache), FieldId { parent, local_id })
+                .expect("field without source");
+            let root = CacheRef::Cached(&cache).parse_or_expand(&db, ptr.file_id);
+            let field = ptr.value.right().expect("record field").to_node(&root);
+            assert_eq!(field.name().unwrap().text(), format!("f{i}"));
+        }
+
+        let map = cache.map.borrow();
+        let lowered = map.map.get::<FxHashMap<VariantId, InFile<def_to_src::FieldPtrs>>>();
+        assert_eq!(lowered.map_or(0, |it| it.len()), 1);
+    }
+
+    #[test]
+    fn invalidate_def_keeps_other_defs() {
+        let (db, _, structs) = structs_of_file("struct A; struct B;");
+        let (a, b) = (structs[0], structs[1]);
+
+        let cache = DefToSrcCache::default();
+        let a_src = source_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, a).unwrap();
+        source_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, b).unwrap();
+
+        cache.invalidate_def(def_to_src::STRUCT, &a);
+        assert!(!cache.contains(def_to_src::STRUCT, &a));
+        assert!(cache.contains(def_to_src::STRUCT, &b));
+
+        let recomputed =
+            source_with_ctx(&db, CacheRef::Cached(&cache), def_to_src::STRUCT, a).unwrap();
+        assert_eq!(recomputed, a_src);
+        assert!(cache.contains(def_to_src::STRUCT, &a));
+    }
+
+    #[test]
+    fn field_ptr_caches_each_field() {
+        let (db, _, structs) = structs_of_file("struct S(u8, u16);");
+        let parent = VariantId::StructId(structs[0]);
+        let field = FieldId { parent, local_id: LocalFieldId::from_raw(RawIdx::from(1)) };
+
+        let cache = DefToSrcCache::default();
+        assert!(!cache.contains(def_to_src::FIELD, &field));
+        let first = field_ptr(&db, CacheRef::Cached(&cache), field).unwrap();
+        assert!(cache.contains(def_to_src::FIELD, &field));
+
+        // Served from the per-field entry, even with the variant's pointers gone.
+        cache
+            .map
+            .borrow_mut()
+            .map
+            .entry::<FxHashMap<VariantId, InFile<def_to_src::FieldPtrs>>>()
+            .or_insert_with(Default::default)
+            .clear();
+        let second = field_ptr(&db, CacheRef::Cached(&cache), field).unwrap();
+        assert_eq!(first, second);
+        assert!(!cache.contains(def_to_src::VARIANT_FIELDS, &parent));
+    }
+
+    #[test]
+    fn type_or_const_param_ptr_collects_params_once() {
+        let (db, file_id) = TestDB::with_single_file(
+            r#"
+trait Tr<A, const B: usize, C> {}
+"#,
+        );
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let trait_ = def_map[module.local_id]
+            .scope
+            .declarations()
+            .find_map(|def| match def {
+                ModuleDefId::TraitId(it) => Some(it),
+                _ => None,
+            })
+            .unwrap();
+        let parent = GenericDefId::TraitId(trait_);
+
+        let cache = DefToSrcCache::default();
+        for _ in 0..3 {
+            // `Self` comes first and points to the trait itself.
+            let names: Vec<String> = (0..4)
+                .map(|i| {
+                    let local_id = LocalTypeOrConstParamId::from_raw(RawIdx::from(i));
+                    let param = TypeOrConstParamId { parent, local_id };
+                    let ptr = type_or_const_param_ptr(&db, CacheRef::Cached(&cache), param)
+                        .expect("param without source");
+                    let root = CacheRef::Cached(&cache).parse_or_expand(&db, ptr.file_id);
+                    let name = match ptr.value {
+                        Either::Left(it) => it.to_node(&root).name(),
+                        Either::Right(it) => it.to_node(&root).name(),
+                    };
+                    name.unwrap().text().to_string()
+                })
+                .collect();
+            assert_eq!(names, ["Tr", "A", "B", "C"]);
+        }
+
+        let map = cache.map.borrow();
+        let collected =
+            map.map.get::<FxHashMap<GenericDefId, InFile<def_to_src::TypeOrConstParamPtrs>>>();
+        assert_eq!(collected.map_or(0, |it| it.len()), 1);
+    }
+
+    #[test]
+    fn type_or_const_param_ptr_caches_trait_source() {
+        let (db, file_id) = TestDB::with_single_file("trait Tr<A> {}");
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let trait_ = def_map[module.local_id]
+            .scope
+            .declarations()
+            .find_map(|def| match def {
+                ModuleDefId::TraitId(it) => Some(it),
+                _ => None,
+            })
+            .unwrap();
+
+        let cache = DefToSrcCache::default();
+        let local_id = LocalTypeOrConstParamId::from_raw(RawIdx::from(0));
+        let param = TypeOrConstParamId { parent: GenericDefId::TraitId(trait_), local_id };
+        type_or_const_param_ptr(&db, CacheRef::Cached(&cache), param).unwrap();
+
+        // Looking up the `Self` parameter resolved the trait's source through the cache, so the
+        // trait's own source lookup doesn't have to go through the item tree again.
+        assert!(cache.contains(def_to_src::TRAIT, &trait_));
+    }
+
+    #[test]
+    fn lifetime_param_ptr_collects_params_once() {
+        let (db, file_id) = TestDB::with_single_file("fn f<'a, T, 'b, const N: usize, 'c>() {}");
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let func = def_map[module.local_id]
+            .scope
+            .declarations()
+            .find_map(|def| match def {
+                ModuleDefId::FunctionId(it) => Some(it),
+                _ => None,
+            })
+            .unwrap();
+        let parent = GenericDefId::FunctionId(func);
+
+        let cache = DefToSrcCache::default();
+        for _ in 0..3 {
+            let names: Vec<String> = (0..3)
+                .map(|i| {
+                    let local_id = LocalLifetimeParamId::from_raw(RawIdx::from(i));
+                    let param = LifetimeParamId { parent, local_id };
+                    let ptr = lifetime_param_ptr(&db, CacheRef::Cached(&cache), param)
+                        .expect("param without source");
+                    let root = CacheRef::Cached(&cache).parse_or_expand(&db, ptr.file_id);
+                    ptr.value.to_node(&root).lifetime().unwrap().text().to_string()
+                })
+                .collect();
+            assert_eq!(names, ["'a", "'b", "'c"]);
+        }
+
+        let map = cache.map.borrow();
+        let collected =
+            map.map.get::<FxHashMap<GenericDefId, InFile<def_to_src::LifetimeParamPtrs>>>();
+        assert_eq!(collected.map_or(0, |it| it.len()), 1);
+    }
+
+    #[test]
+    fn labels_of_a_body_share_its_source_map() {
+        let (db, file_id) = TestDB::with_single_file(
+            r#"
+fn f() { 'a: loop { 'b: loop {} } }
+fn g() { 'c: loop { 'd: loop {} } }
+"#,
+        );
+        let module = db.module_for_file(file_id);
+        let def_map = module.def_map(&db);
+        let (f, g) = def_map[module.local_id]
+            .scope
+            .declarations()
+            .filter_map(|def| match def {
+                ModuleDefId::FunctionId(it) => Some(DefWithBodyId::from(it)),
+                _ => None,
+            })
+            .collect_tuple()
+            .unwrap();
+        let labels = db.body(f).labels.iter().map(|(id, _)| id).collect::<Vec<_>>();
+        let root = db.parse_or_expand(file_id.into());
+        let cache = DefToSrcCache::default();
+        let cached = || {
+            labels
+                .iter()
+                .map(|&label| {
+                    label_ptr(&db, CacheRef::Cached(&cache), f, label)
+                        .value
+                        .to_node(&root)
+                        .to_string()
+                })
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(cached(), ["'a:", "'b:"]);
+        assert!(cache.contains(def_to_src::BODY_SOURCE_MAP, &f));
+
+        // Both labels of `f` are looked up through the cached source map, so replacing it with the
+        // one of `g` makes them resolve to the labels of `g`.
+        cache.insert(def_to_src::BODY_SOURCE_MAP, f, db.body_with_source_map(g).1);
+        assert_eq!(cached(), ["'c:", "'d:"]);
+        let uncached = labels
+            .iter()
+            .map(|&label| {
+                let ptr = label_ptr(&db, CacheRef::<DefToSrcCache>::Uncached, f, label);
+                ptr.value.to_node(&root).to_string()
+            })
+            .sorted()
+            .collect::<Vec<_>>();
+        assert_eq!(uncached, ["'a:", "'b:"]);
+    }
+}
@@ -14,9 +14,10 @@ use hir_expand::{mod_path::PathKind, name::Name};
 use hir_ty::{db::HirDatabase, method_resolution};
 
 use crate::{
-    Adt, AsAssocItem, AssocItem, BuiltinType, Const, ConstParam, DocLinkDef, Enum, ExternCrateDecl,
-    Field, Function, GenericParam, HasCrate, Impl, LifetimeParam, Macro, Module, ModuleDef, Static,
-    Struct, Trait, TraitAlias, Type, TypeAlias, TypeParam, Union, Variant, VariantDef,
+    semantics::AnyDef, Adt, AsAssocItem, AssocItem, BuiltinType, Const, ConstParam, DocLinkDef,
+    Enum, ExternCrateDecl, Field, Function, GenericParam, HasCrate, Impl, LifetimeParam, Macro,
+    Module, ModuleDef, Static, Struct, Trait, TraitAlias, Type, TypeAlias, TypeParam, Union,
+    Variant, VariantDef,
 };
 
 pub trait HasAttrs {
@@ -99,6 +100,66 @@ pub fn resolve_doc_path_on(
     resolve_doc_path_on_(db, link, def.attr_id(), ns)
 }
 
+/// Resolves the intra-doc link `link` in the scope of `def`, honoring disambiguators like
+/// `fn@foo` or `foo!`.
+pub(crate) fn resolve_doc_link_on_any_def(
+    db: &dyn HirDatabase,
+    def: AnyDef,
+    link: &str,
+) -> Option<AnyDef> {
+    let attr_id = match def {
+        AnyDef::Module(it) => it.attr_id(),
+        AnyDef::Function(it) => it.attr_id(),
+        AnyDef::Adt(it) => it.attr_id(),
+        AnyDef::Variant(it) => it.attr_id(),
+        AnyDef::Field(it) => it.attr_id(),
+        AnyDef::Const(it) => it.attr_id(),
+        AnyDef::Static(it) => it.attr_id(),
+        AnyDef::Trait(it) => it.attr_id(),
+        AnyDef::TraitAlias(it) => it.attr_id(),
+        AnyDef::TypeAlias(it) => it.attr_id(),
+        AnyDef::Impl(it) => it.attr_id(),
+        AnyDef::Macro(it) => it.attr_id(),
+        AnyDef::GenericParam(it) => it.attr_id(),
+        AnyDef::ExternCrateDecl(it) => it.attr_id(),
+        AnyDef::Local(_) | AnyDef::Label(_) | AnyDef::BuiltinAttr(_) | AnyDef::ToolModule(_) => {
+            return None
+        }
+    };
+    let (link, ns) = parse_doc_link_namespace(link);
+    let def = match resolve_doc_path_on_(db, link, attr_id, ns)? {
+        DocLinkDef::ModuleDef(it) => AnyDef::from_module_def(it)?,
+        DocLinkDef::Field(it) => it.into(),
+        DocLinkDef::SelfType(it) => it.into(),
+    };
+    Some(def)
+}
+
+/// Splits the disambiguator off an intra-doc link: `struct Foo` names a type, `fn@foo` and
+/// `foo()` name a value and `foo!` names a macro.
+fn parse_doc_link_namespace(link: &str) -> (&str, Option<Namespace>) {
+    let link = link.trim_matches('`');
+    if let Some((prefix, path)) = link.split_once(['@', ' ']) {
+        let ns = match prefix {
+            "type" | "struct" | "enum" | "mod" | "module" | "trait" | "union" | "prim"
+            | "primitive" => Some(Namespace::Types),
+            "value" | "function" | "fn" | "method" | "const" | "static" => Some(Namespace::Values),
+            "macro" | "derive" => Some(Namespace::Macros),
+            _ => None,
+        };
+        if ns.is_some() {
+            return (path, ns);
+        }
+    }
+    if let Some(path) = link.strip_suffix("()") {
+        return (path, Some(Namespace::Values));
+    }
+    if let Some(path) = link.strip_suffix('!') {
+        return (path, Some(Namespace::Macros));
+    }
+    (link, None)
+}
+
 fn resolve_doc_path_on_(
     db: &dyn HirDatabase,
     link: &str,
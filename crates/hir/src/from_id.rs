@@ -48,6 +48,7 @@ from_id![
     (hir_def::ConstParamId, crate::ConstParam),
     (hir_def::LifetimeParamId, crate::LifetimeParam),
     (hir_def::MacroId, crate::Macro),
+    (hir_def::ExternBlockId, crate::ExternBlock),
     (hir_def::ExternCrateId, crate::ExternCrateDecl),
 ];
 
@@ -3,19 +3,26 @@
 use base_db::FileId;
 use either::Either;
 use hir_def::{
-    nameres::{ModuleOrigin, ModuleSource},
-    src::{HasChildSource, HasSource as _},
-    Lookup, MacroId, VariantId,
+    dyn_map::def_to_src,
+    nameres::{DefMap, ModuleOrigin, ModuleSource},
+    src::{self, CacheRef, DefToSrcCache, HasChildSource, HasSource as _},
+    FieldId, Lookup, MacroId, TypeOrConstParamId, VariantId,
 };
 use hir_expand::{HirFileId, InFile};
 use hir_ty::{db::InternedClosure, CallableDefId};
-use syntax::ast;
+use stdx::never;
+use syntax::{
+    ast::{self, HasAttrs as _},
+    AstNode as _, SyntaxKind,
+};
 use tt::TextRange;
 
 use crate::{
-    db::HirDatabase, Adt, Callee, Const, Enum, ExternCrateDecl, Field, FieldSource, Function, Impl,
-    Label, LifetimeParam, LocalSource, Macro, Module, Param, SelfParam, Static, Struct, Trait,
-    TraitAlias, TypeAlias, TypeOrConstParam, Union, Variant,
+    db::{DefDatabase, HirDatabase},
+    Adt, BuiltinType, Callable, Callee, Const, Crate, DeriveHelper, Enum, ExternCrateDecl, Field,
+    FieldSource, Function, Impl, Label, LifetimeParam, LocalSource, Macro, Module, Param,
+    SelfParam, SemanticsImpl, Static, Struct, ToolModule, Trait, TraitAlias, TypeAlias,
+    TypeOrConstParam, Union, Variant,
 };
 
 pub trait HasSource {
@@ -28,6 +35,19 @@ pub trait HasSource {
     /// But we made this method `Option` to support rlib in the future
     /// by <https://github.com/rust-lang/rust-analyzer/issues/6913>
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>>;
+
+    /// Like [`HasSource::source`], but looks up the definition's source pointer through `ctx`,
+    /// so that repeated lookups of the same definition don't have to go through the item tree.
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        _ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>>
+    where
+        Self: Sized,
+    {
+        self.source(db)
+    }
 }
 
 /// NB: Module is !HasSource, because it has two source nodes at the same time:
@@ -88,6 +108,37 @@ impl Module {
     }
 }
 
+impl Crate {
+    /// Returns the source file of the crate's root module, e.g. its `lib.rs` or `main.rs`.
+    pub fn root_source(self, db: &dyn HirDatabase) -> Option<InFile<ast::SourceFile>> {
+        let def_map = db.crate_def_map(self.id);
+        let ModuleOrigin::CrateRoot { definition } = def_map[DefMap::ROOT].origin else {
+            never!("the root module of a crate doesn't originate from its root file");
+            return None;
+        };
+        Some(InFile::new(definition.into(), db.parse(definition).tree()))
+    }
+}
+
+impl Callable {
+    /// Returns the tuple struct or tuple enum variant a constructor call refers to, or `None` if
+    /// this isn't a constructor.
+    pub fn constructor_source(
+        &self,
+        db: &dyn HirDatabase,
+    ) -> Option<InFile<Either<ast::Struct, ast::Variant>>> {
+        match self.callee {
+            Callee::Def(CallableDefId::StructId(id)) => {
+                Some(Struct { id }.source(db)?.map(Either::Left))
+            }
+            Callee::Def(CallableDefId::EnumVariantId(id)) => {
+                Some(Variant { id }.source(db)?.map(Either::Right))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl HasSource for Field {
     type Ast = FieldSource;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
@@ -99,6 +150,21 @@ impl HasSource for Field {
         });
         Some(field_source)
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        let ctx = CacheRef::Cached(ctx);
+        let field = FieldId { parent: self.parent.into(), local_id: self.id };
+        let InFile { file_id, value } = src::field_ptr(db.upcast(), ctx, field)?;
+        let root = ctx.parse_or_expand(db.upcast(), file_id);
+        let field_source = match value {
+            Either::Left(it) => FieldSource::Pos(src::ptr_to_node(&root, &it)?),
+            Either::Right(it) => FieldSource::Named(src::ptr_to_node(&root, &it)?),
+        };
+        Some(InFile::new(file_id, field_source))
+    }
 }
 impl HasSource for Adt {
     type Ast = ast::Adt;
@@ -109,66 +175,180 @@ impl HasSource for Adt {
             Adt::Enum(e) => Some(e.source(db)?.map(ast::Adt::Enum)),
         }
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        match self {
+            Adt::Struct(s) => Some(s.source_with_ctx(db, ctx)?.map(ast::Adt::Struct)),
+            Adt::Union(u) => Some(u.source_with_ctx(db, ctx)?.map(ast::Adt::Union)),
+            Adt::Enum(e) => Some(e.source_with_ctx(db, ctx)?.map(ast::Adt::Enum)),
+        }
+    }
 }
 impl HasSource for Struct {
     type Ast = ast::Struct;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::STRUCT, self.id)
+    }
 }
 impl HasSource for Union {
     type Ast = ast::Union;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::UNION, self.id)
+    }
 }
 impl HasSource for Enum {
     type Ast = ast::Enum;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::ENUM, self.id)
+    }
 }
 impl HasSource for Variant {
     type Ast = ast::Variant;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<ast::Variant>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        let ctx = CacheRef::Cached(ctx);
+        src::source_with_ctx(db.upcast(), ctx, def_to_src::ENUM_VARIANT, self.id)
+    }
+}
+impl Variant {
+    /// Returns the sources of the variant's fields in declaration order, which is empty for unit
+    /// variants. The fields are looked up through `sema`, so that they share its cached parse.
+    pub fn field_sources(self, sema: &SemanticsImpl<'_>) -> Vec<(Field, FieldSource)> {
+        self.fields(sema.db)
+            .into_iter()
+            .filter_map(|field| Some((field, sema.source(field)?.value)))
+            .collect()
+    }
+
+    /// Returns the explicit discriminant of the variant, like the `1` in `enum E { A = 1 }`, or
+    /// `None` if the discriminant is implicit.
+    pub fn discriminant_source(self, sema: &SemanticsImpl<'_>) -> Option<InFile<ast::Expr>> {
+        let src = sema.source(self)?;
+        src.value.expr().map(|it| src.with_value(it))
+    }
 }
+
 impl HasSource for Function {
     type Ast = ast::Fn;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::FUNCTION, self.id)
+    }
 }
 impl HasSource for Const {
     type Ast = ast::Const;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::CONST, self.id)
+    }
 }
 impl HasSource for Static {
     type Ast = ast::Static;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::STATIC, self.id)
+    }
 }
 impl HasSource for Trait {
     type Ast = ast::Trait;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::TRAIT, self.id)
+    }
+}
+impl Trait {
+    /// Returns the source of the trait's implicit `Self` type parameter. `Self` has no syntax of
+    /// its own, so the parameter maps to the trait itself.
+    pub fn self_param_source(self, sema: &SemanticsImpl<'_>) -> Option<InFile<ast::Trait>> {
+        let local_id = sema.db.generic_params(self.id.into()).find_trait_self_param()?;
+        let param =
+            TypeOrConstParam { id: TypeOrConstParamId { parent: self.id.into(), local_id } };
+        let InFile { file_id, value } = sema.source(param)?;
+        match value {
+            Either::Right(ast::TraitOrAlias::Trait(it)) => Some(InFile::new(file_id, it)),
+            _ => None,
+        }
+    }
 }
 impl HasSource for TraitAlias {
     type Ast = ast::TraitAlias;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::TRAIT_ALIAS, self.id)
+    }
 }
 impl HasSource for TypeAlias {
     type Ast = ast::TypeAlias;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::TYPE_ALIAS, self.id)
+    }
 }
 impl HasSource for Macro {
     type Ast = Either<ast::Macro, ast::Fn>;
@@ -191,12 +371,84 @@ impl HasSource for Macro {
             }
         }
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        let db = db.upcast();
+        let ctx = CacheRef::Cached(ctx);
+        Some(match self.id {
+            MacroId::Macro2Id(it) => src::source_with_ctx(db, ctx, def_to_src::MACRO2, it)?
+                .map(ast::Macro::MacroDef)
+                .map(Either::Left),
+            MacroId::MacroRulesId(it) => {
+                src::source_with_ctx(db, ctx, def_to_src::MACRO_RULES, it)?
+                    .map(ast::Macro::MacroRules)
+                    .map(Either::Left)
+            }
+            MacroId::ProcMacroId(it) => {
+                src::source_with_ctx(db, ctx, def_to_src::PROC_MACRO, it)?.map(Either::Right)
+            }
+        })
+    }
+}
+impl HasSource for DeriveHelper {
+    type Ast = ast::Attr;
+    /// Returns the attribute declaring the helper on the derive macro's definition, that is its
+    /// `#[proc_macro_derive(Name, attributes(...))]` or `#[rustc_builtin_macro(...)]`.
+    fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
+        let InFile { file_id, value } = self.derive().source(db)?;
+        let mut attrs = match &value {
+            Either::Left(it) => it.attrs(),
+            Either::Right(it) => it.attrs(),
+        };
+        let attr = attrs.find(|attr| {
+            matches!(
+                attr.simple_name().as_deref(),
+                Some("proc_macro_derive" | "rustc_builtin_macro")
+            )
+        })?;
+        Some(InFile { file_id, value: attr })
+    }
 }
 impl HasSource for Impl {
     type Ast = ast::Impl;
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::IMPL, self.id)
+    }
+}
+
+impl Impl {
+    /// Returns the trait being implemented, like the `Tr<u8>` in `impl Tr<u8> for S {}`, or
+    /// `None` for inherent impls.
+    pub fn trait_source(self, sema: &SemanticsImpl<'_>) -> Option<InFile<ast::Type>> {
+        let src = sema.source(self)?;
+        src.value.trait_().map(|it| src.with_value(it))
+    }
+
+    /// Returns the type the impl is for, like the `S` in `impl Tr for S {}` or `impl S {}`.
+    pub fn self_ty_source(self, sema: &SemanticsImpl<'_>) -> Option<InFile<ast::Type>> {
+        let src = sema.source(self)?;
+        src.value.self_ty().map(|it| src.with_value(it))
+    }
+}
+
+impl TraitAlias {
+    /// Returns the bounds the alias stands for in declaration order, like `Clone` and `Debug` in
+    /// `trait Alias = Clone + Debug;`. Lifetime bounds have no type and are skipped.
+    pub fn bound_sources(self, sema: &SemanticsImpl<'_>) -> Vec<InFile<ast::Type>> {
+        let Some(src) = sema.source(self) else { return Vec::new() };
+        let Some(bounds) = src.value.type_bound_list() else { return Vec::new() };
+        bounds.bounds().filter_map(|bound| bound.ty()).map(|ty| src.with_value(ty)).collect()
+    }
 }
 
 impl HasSource for TypeOrConstParam {
@@ -205,6 +457,20 @@ impl HasSource for TypeOrConstParam {
         let child_source = self.id.parent.child_source(db.upcast());
         child_source.map(|it| it.get(self.id.local_id).cloned()).transpose()
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        let ctx = CacheRef::Cached(ctx);
+        let InFile { file_id, value } = src::type_or_const_param_ptr(db.upcast(), ctx, self.id)?;
+        let root = ctx.parse_or_expand(db.upcast(), file_id);
+        let value = match value {
+            Either::Left(it) => Either::Left(src::ptr_to_node(&root, &it)?),
+            Either::Right(it) => Either::Right(src::ptr_to_node(&root, &it)?),
+        };
+        Some(InFile::new(file_id, value))
+    }
 }
 
 impl HasSource for LifetimeParam {
@@ -213,6 +479,16 @@ impl HasSource for LifetimeParam {
         let child_source = self.id.parent.child_source(db.upcast());
         child_source.map(|it| it.get(self.id.local_id).cloned()).transpose()
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        let ctx = CacheRef::Cached(ctx);
+        let InFile { file_id, value } = src::lifetime_param_ptr(db.upcast(), ctx, self.id)?;
+        let root = ctx.parse_or_expand(db.upcast(), file_id);
+        Some(InFile::new(file_id, src::ptr_to_node(&root, &value)?))
+    }
 }
 
 impl HasSource for LocalSource {
@@ -245,19 +521,27 @@ impl HasSource for Param {
             Callee::Closure(closure, _) => {
                 let InternedClosure(owner, expr_id) = db.lookup_intern_closure(closure.into());
                 let (_, source_map) = db.body_with_source_map(owner);
-                let ast @ InFile { file_id, value } = source_map.expr_syntax(expr_id).ok()?;
+                // The closure may have been emitted by a macro, in which case `file_id` is the
+                // macro file and `parse_or_expand` gives us its expansion.
+                let InFile { file_id, value } = source_map.expr_syntax(expr_id).ok()?;
                 let root = db.parse_or_expand(file_id);
                 match value.to_node(&root) {
-                    ast::Expr::ClosureExpr(it) => it
-                        .param_list()?
-                        .params()
-                        .nth(self.idx)
-                        .map(Either::Right)
-                        .map(|value| InFile { file_id: ast.file_id, value }),
+                    ast::Expr::ClosureExpr(it) => {
+                        let param = it.param_list()?.params().nth(self.idx);
+                        if param.is_none() {
+                            never!("closure parameter index {} is out of bounds", self.idx);
+                        }
+                        param.map(|value| InFile { file_id, value: Either::Right(value) })
+                    }
                     _ => None,
                 }
             }
-            _ => None,
+            // Tuple struct and enum variant constructors take their parameters from tuple fields,
+            // see `Param::as_tuple_field`, while function pointers and `Fn*` trait calls have no
+            // parameter syntax at all.
+            Callee::Def(CallableDefId::StructId(_) | CallableDefId::EnumVariantId(_))
+            | Callee::FnPtr
+            | Callee::FnImpl(_) => None,
         }
     }
 }
@@ -283,6 +567,17 @@ impl HasSource for Label {
         let root = src.file_syntax(db.upcast());
         Some(src.map(|ast| ast.to_node(&root)))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        let ctx = CacheRef::Cached(ctx);
+        let src = src::label_ptr(db.upcast(), ctx, self.parent, self.label_id);
+        let root = ctx.parse_or_expand(db.upcast(), src.file_id);
+        let node = src::ptr_to_node(&root, &src.value)?;
+        Some(src.with_value(node))
+    }
 }
 
 impl HasSource for ExternCrateDecl {
@@ -291,4 +586,40 @@ impl HasSource for ExternCrateDecl {
     fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
         Some(self.id.lookup(db.upcast()).source(db.upcast()))
     }
+    fn source_with_ctx(
+        self,
+        db: &dyn HirDatabase,
+        ctx: &DefToSrcCache,
+    ) -> Option<InFile<Self::Ast>> {
+        src::source_with_ctx(db.upcast(), CacheRef::Cached(ctx), def_to_src::EXTERN_CRATE, self.id)
+    }
+}
+
+impl HasSource for ToolModule {
+    type Ast = ast::Attr;
+    /// Returns the `#![register_tool(..)]` attribute of the crate root that registers the tool.
+    /// Tools known to the compiler, like `rustfmt`, aren't registered anywhere and have no source.
+    fn source(self, db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
+        let root = Crate { id: self.krate }.root_source(db)?;
+        let name = self.name(db);
+        let attr = root.value.attrs().find(|attr| {
+            attr.kind() == ast::AttrKind::Inner
+                && attr.simple_name().as_deref() == Some("register_tool")
+                && attr.token_tree().map_or(false, |tt| {
+                    tt.syntax()
+                        .children_with_tokens()
+                        .filter_map(|it| it.into_token())
+                        .any(|it| it.kind() == SyntaxKind::IDENT && it.text() == name)
+                })
+        })?;
+        Some(root.with_value(attr))
+    }
+}
+
+impl HasSource for BuiltinType {
+    type Ast = ast::Type;
+    /// Builtin types are provided by the compiler and have no source.
+    fn source(self, _db: &dyn HirDatabase) -> Option<InFile<Self::Ast>> {
+        None
+    }
 }
@@ -53,10 +53,10 @@ use hir_def::{
     per_ns::PerNs,
     resolver::{HasResolver, Resolver},
     AssocItemId, AssocItemLoc, AttrDefId, ConstId, ConstParamId, CrateRootModuleId, DefWithBodyId,
-    EnumId, EnumVariantId, ExternCrateId, FunctionId, GenericDefId, GenericParamId, HasModule,
-    ImplId, InTypeConstId, ItemContainerId, LifetimeParamId, LocalFieldId, Lookup, MacroExpander,
-    ModuleId, StaticId, StructId, TraitAliasId, TraitId, TupleId, TypeAliasId, TypeOrConstParamId,
-    TypeParamId, UnionId,
+    EnumId, EnumVariantId, ExternBlockId, ExternCrateId, FunctionId, GenericDefId, GenericParamId,
+    HasModule, ImplId, InTypeConstId, ItemContainerId, LifetimeParamId, LocalFieldId, Lookup,
+    MacroExpander, ModuleId, StaticId, StructId, TraitAliasId, TraitId, TupleId, TypeAliasId,
+    TypeOrConstParamId, TypeParamId, UnionId,
 };
 use hir_expand::{
     attrs::collect_attrs, name::name, proc_macro::ProcMacroKind, AstId, MacroCallKind, ValueResult,
@@ -94,8 +94,9 @@ pub use crate::{
     diagnostics::*,
     has_source::HasSource,
     semantics::{
-        DescendPreference, PathResolution, Semantics, SemanticsImpl, SemanticsScope, TypeInfo,
-        VisibleTraits,
+        AnyDef, CacheStats, DescendPreference, EvictedEntry, HygieneOrigin, ItemResolution,
+        PathResolution, ResolveOutcome, ResolveResult, Semantics, SemanticsImpl, SemanticsScope,
+        TypeInfo, VisibleTraits,
     },
 };
 pub use hir_ty::method_resolution::TyFingerprint;
@@ -134,8 +135,8 @@ pub use {
         inert_attr_macro::AttributeTemplate,
         name::{known, Name},
         proc_macro::ProcMacros,
-        tt, ExpandResult, HirFileId, HirFileIdExt, InFile, InMacroFile, InRealFile, MacroFileId,
-        MacroFileIdExt,
+        tt, ExpandResult, ExpansionInfo, HirFileId, HirFileIdExt, InFile, InMacroFile, InRealFile,
+        MacroFileId, MacroFileIdExt,
     },
     hir_ty::{
         consteval::ConstEvalError,
@@ -1092,7 +1093,7 @@ impl TupleField {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FieldSource {
     Named(ast::RecordField),
     Pos(ast::TupleField),
@@ -2079,6 +2080,16 @@ impl Function {
         db.function_data(self.id).has_async_kw()
     }
 
+    /// Returns the ABI of the function, e.g. `C` for an `extern "C" fn` or for a function declared
+    /// in an `extern "C" {}` block, see [`ExternBlock::abi`]. A bare `extern` means `C`. `None` if
+    /// no ABI is given, that is for plain Rust functions.
+    pub fn abi(self, db: &dyn HirDatabase) -> Option<SmolStr> {
+        match self.id.lookup(db.upcast()).container {
+            ItemContainerId::ExternBlockId(id) => ExternBlock { id }.abi(db),
+            _ => db.function_data(self.id).abi.as_deref().map(SmolStr::new),
+        }
+    }
+
     /// Does this function have `#[test]` attribute?
     pub fn is_test(self, db: &dyn HirDatabase) -> bool {
         db.function_data(self.id).attrs.is_test()
@@ -2247,6 +2258,17 @@ impl Param {
     pub fn pattern_source(self, db: &dyn HirDatabase) -> Option<ast::Pat> {
         self.source(db).and_then(|p| p.value.right()?.pat())
     }
+
+    /// Returns the field this parameter initializes if the callee is the constructor of a tuple
+    /// struct or tuple enum variant. These parameters have no `ast::Param` source.
+    pub fn as_tuple_field(&self, db: &dyn HirDatabase) -> Option<Field> {
+        let parent = match self.func {
+            Callee::Def(CallableDefId::StructId(it)) => VariantDef::Struct(it.into()),
+            Callee::Def(CallableDefId::EnumVariantId(it)) => VariantDef::Variant(it.into()),
+            _ => return None,
+        };
+        parent.fields(db).into_iter().nth(self.idx)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -2319,6 +2341,23 @@ impl HasVisibility for Function {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExternBlock {
+    pub(crate) id: ExternBlockId,
+}
+
+impl ExternBlock {
+    pub fn module(self, db: &dyn HirDatabase) -> Module {
+        Module { id: self.id.lookup(db.upcast()).container }
+    }
+
+    /// Returns the ABI of the block, e.g. `C` for `extern "C" {}`. A bare `extern {}` means `C`.
+    pub fn abi(self, db: &dyn HirDatabase) -> Option<SmolStr> {
+        let loc = self.id.lookup(db.upcast());
+        loc.id.item_tree(db.upcast())[loc.id.value].abi.as_deref().map(SmolStr::new)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ExternCrateDecl {
     pub(crate) id: ExternCrateId,
@@ -3403,7 +3442,7 @@ impl BuiltinAttr {
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ToolModule {
-    krate: CrateId,
+    pub(crate) krate: CrateId,
     idx: u32,
 }
 
@@ -5380,7 +5419,7 @@ impl HasContainer for TraitAlias {
 
 fn container_id_to_hir(c: ItemContainerId) -> ItemContainer {
     match c {
-        ItemContainerId::ExternBlockId(_id) => ItemContainer::ExternBlock(),
+        ItemContainerId::ExternBlockId(id) => ItemContainer::ExternBlock(ExternBlock { id }),
         ItemContainerId::ModuleId(id) => ItemContainer::Module(Module { id }),
         ItemContainerId::ImplId(id) => ItemContainer::Impl(Impl { id }),
         ItemContainerId::TraitId(id) => ItemContainer::Trait(Trait { id }),
@@ -5392,7 +5431,7 @@ pub enum ItemContainer {
     Trait(Trait),
     Impl(Impl),
     Module(Module),
-    ExternBlock(),
+    ExternBlock(ExternBlock),
     Crate(CrateId),
 }
 
@@ -3,9 +3,10 @@
 mod source_to_def;
 
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     fmt, iter, mem,
     ops::{self, ControlFlow, Not},
+    rc::Rc,
 };
 
 use base_db::{FileId, FileRange};
@@ -15,6 +16,7 @@ use hir_def::{
     lower::LowerCtx,
     nameres::MacroSubNs,
     resolver::{self, HasResolver, Resolver, TypeNs},
+    src::{DefToSrcCache, SrcDefCacheContext},
     type_ref::Mutability,
     AsMacroCall, DefWithBodyId, FunctionId, MacroId, TraitId, VariantId,
 };
@@ -23,19 +25,20 @@ use hir_expand::{
     builtin_fn_macro::{BuiltinFnLikeExpander, EagerExpander},
     db::ExpandDatabase,
     files::InRealFile,
+    mod_path::{ModPath, PathKind},
     name::AsName,
-    InMacroFile, MacroCallId, MacroFileId, MacroFileIdExt,
+    ExpansionInfo, InMacroFile, MacroCallId, MacroFileId, MacroFileIdExt,
 };
 use itertools::Itertools;
 use rustc_hash::{FxHashMap, FxHashSet};
 use smallvec::{smallvec, SmallVec};
 use span::{Span, SyntaxContextId, ROOT_ERASED_FILE_AST_ID};
-use stdx::TupleExt;
+use stdx::{impl_from, TupleExt};
 use syntax::{
     algo::skip_trivia_token,
     ast::{self, HasAttrs as _, HasGenericParams, HasLoopBody, IsString as _},
     match_ast, AstNode, AstToken, Direction, SyntaxKind, SyntaxNode, SyntaxNodePtr, SyntaxToken,
-    TextRange, TextSize,
+    TextRange, TextSize, T,
 };
 
 use crate::{
@@ -43,10 +46,11 @@ use crate::{
     semantics::source_to_def::{ChildContainer, SourceToDefCache, SourceToDefCtx},
     source_analyzer::{resolve_hir_path, SourceAnalyzer},
     Access, Adjust, Adjustment, Adt, AutoBorrow, BindingMode, BuiltinAttr, Callable, Const,
-    ConstParam, Crate, DeriveHelper, Enum, Field, Function, HasSource, HirFileId, Impl, InFile,
-    Label, LifetimeParam, Local, Macro, Module, ModuleDef, Name, OverloadedDeref, Path, ScopeDef,
-    Static, Struct, ToolModule, Trait, TraitAlias, TupleField, Type, TypeAlias, TypeParam, Union,
-    Variant, VariantDef,
+    ConstParam, Crate, DefWithBody, DeriveHelper, Enum, ExternCrateDecl, Field, FieldSource,
+    Function, GenericDef, GenericParam, HasSource, HirFileId, Impl, InFile, Label, LifetimeParam,
+    Local, Macro, Module, ModuleDef, Name, OverloadedDeref, Path, ScopeDef, Static, Struct,
+    ToolModule, Trait, TraitAlias, TupleField, Type, TypeAlias, TypeParam, Union, Variant,
+    VariantDef,
 };
 
 pub enum DescendPreference {
@@ -55,6 +59,80 @@ pub enum DescendPreference {
     None,
 }
 
+/// Where an identifier in a macro expansion was written, see
+/// [`SemanticsImpl::hygiene_origin`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HygieneOrigin {
+    /// The identifier was passed to the macro by its caller.
+    CallSite,
+    /// The identifier stems from the macro definition itself.
+    DefSite,
+}
+
+/// What an item resolves to, see [`SemanticsImpl::resolve_or_expand`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemResolution {
+    /// The item is a definition.
+    Def(AnyDef),
+    /// The item is the input of an attribute macro, which replaces it with its expansion.
+    MacroCall(MacroFileId),
+}
+
+/// What a node resolves to, see [`SemanticsImpl::node_to_def_outcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveOutcome {
+    /// The node declares a definition.
+    Def(AnyDef),
+    /// The node declares nothing because it, or one of its ancestors, is disabled by a `#[cfg]`.
+    CfgDisabled,
+    /// The node doesn't declare a definition.
+    Unresolved,
+}
+
+/// The definitions a node resolves to, see [`SemanticsImpl::node_to_def_detailed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveResult {
+    /// The definition [`SemanticsImpl::node_to_def`] resolves to.
+    pub primary: AnyDef,
+    /// The definitions the node declares in the other crates its file is part of, e.g. when
+    /// the file is included by several crates through `#[path]` modules.
+    pub alternatives: SmallVec<[AnyDef; 1]>,
+}
+
+/// A source-to-def child map that was dropped from the cache, see
+/// [`SemanticsImpl::set_on_evict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictedEntry {
+    /// The file the dropped map was built for.
+    pub file_id: HirFileId,
+}
+
+/// Hit and miss counts of the source-to-def child map cache, and how many macro expansions and
+/// container searches it had to compute, see [`SemanticsImpl::source_to_def_stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups that were served by an already built child map.
+    pub hits: usize,
+    /// Lookups that had to build a child map.
+    pub misses: usize,
+    /// Macro expansions whose [`ExpansionInfo`](hir_expand::ExpansionInfo) had to be computed.
+    pub expansions: usize,
+    /// Container searches that weren't served by the container cache and had to walk the
+    /// ancestors of the node.
+    pub container_searches: usize,
+}
+
+impl CacheStats {
+    /// The fraction of lookups that were served from the cache, `1.0` if there were none.
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 1.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum PathResolution {
     /// An item
@@ -102,6 +180,115 @@ impl PathResolution {
     }
 }
 
+/// A definition declared by a syntax node, see [`SemanticsImpl::node_to_def`]. Attribute paths
+/// can additionally resolve to builtin attributes and tools, which aren't declared anywhere, see
+/// [`SemanticsImpl::resolve_attr_path`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AnyDef {
+    Module(Module),
+    Function(Function),
+    Adt(Adt),
+    Variant(Variant),
+    Field(Field),
+    Const(Const),
+    Static(Static),
+    Trait(Trait),
+    TraitAlias(TraitAlias),
+    TypeAlias(TypeAlias),
+    Impl(Impl),
+    Macro(Macro),
+    GenericParam(GenericParam),
+    Local(Local),
+    Label(Label),
+    ExternCrateDecl(ExternCrateDecl),
+    BuiltinAttr(BuiltinAttr),
+    ToolModule(ToolModule),
+}
+impl_from!(
+    Module,
+    Function,
+    Adt(Struct, Enum, Union),
+    Variant,
+    Field,
+    Const,
+    Static,
+    Trait,
+    TraitAlias,
+    TypeAlias,
+    Impl,
+    Macro,
+    GenericParam(TypeParam, ConstParam, LifetimeParam),
+    Local,
+    Label,
+    ExternCrateDecl
+    for AnyDef
+);
+
+impl AnyDef {
+    /// Converts a [`ModuleDef`]. Builtin types aren't declared anywhere and convert to `None`.
+    pub(crate) fn from_module_def(def: ModuleDef) -> Option<AnyDef> {
+        let def = match def {
+            ModuleDef::Module(it) => it.into(),
+            ModuleDef::Function(it) => it.into(),
+            ModuleDef::Adt(it) => it.into(),
+            ModuleDef::Variant(it) => it.into(),
+            ModuleDef::Const(it) => it.into(),
+            ModuleDef::Static(it) => it.into(),
+            ModuleDef::Trait(it) => it.into(),
+            ModuleDef::TraitAlias(it) => it.into(),
+            ModuleDef::TypeAlias(it) => it.into(),
+            ModuleDef::Macro(it) => it.into(),
+            ModuleDef::BuiltinType(_) => return None,
+        };
+        Some(def)
+    }
+
+    /// Converts a [`PathResolution`]. Resolutions that aren't declared anywhere, like builtins
+    /// and tool modules, convert to `None`.
+    pub(crate) fn from_path_resolution(res: PathResolution) -> Option<AnyDef> {
+        match res {
+            PathResolution::Def(def) => AnyDef::from_module_def(def),
+            PathResolution::Local(it) => Some(it.into()),
+            PathResolution::TypeParam(it) => Some(GenericParam::from(it).into()),
+            PathResolution::ConstParam(it) => Some(GenericParam::from(it).into()),
+            PathResolution::SelfType(it) => Some(it.into()),
+            PathResolution::BuiltinAttr(_)
+            | PathResolution::ToolModule(_)
+            | PathResolution::DeriveHelper(_) => None,
+        }
+    }
+
+    /// Returns whether the definition was written by a macro expansion rather than directly in a
+    /// source file.
+    pub fn is_from_macro(self, db: &dyn HirDatabase) -> bool {
+        fn file_id<T: HasSource>(def: T, db: &dyn HirDatabase) -> Option<HirFileId> {
+            Some(def.source(db)?.file_id)
+        }
+        let file_id = match self {
+            AnyDef::Module(it) => Some(it.definition_source_file_id(db)),
+            AnyDef::Function(it) => file_id(it, db),
+            AnyDef::Adt(it) => file_id(it, db),
+            AnyDef::Variant(it) => file_id(it, db),
+            AnyDef::Field(it) => file_id(it, db),
+            AnyDef::Const(it) => file_id(it, db),
+            AnyDef::Static(it) => file_id(it, db),
+            AnyDef::Trait(it) => file_id(it, db),
+            AnyDef::TraitAlias(it) => file_id(it, db),
+            AnyDef::TypeAlias(it) => file_id(it, db),
+            AnyDef::Impl(it) => file_id(it, db),
+            AnyDef::Macro(it) => file_id(it, db),
+            AnyDef::GenericParam(GenericParam::TypeParam(it)) => file_id(it.merge(), db),
+            AnyDef::GenericParam(GenericParam::ConstParam(it)) => file_id(it.merge(), db),
+            AnyDef::GenericParam(GenericParam::LifetimeParam(it)) => file_id(it, db),
+            AnyDef::Local(it) => Some(it.primary_source(db).file()),
+            AnyDef::Label(it) => file_id(it, db),
+            AnyDef::ExternCrateDecl(it) => file_id(it, db),
+            AnyDef::BuiltinAttr(_) | AnyDef::ToolModule(_) => None,
+        };
+        file_id.map_or(false, |it| it.macro_file().is_some())
+    }
+}
+
 #[derive(Debug)]
 pub struct TypeInfo {
     /// The original type of the expression or pattern.
@@ -134,10 +321,16 @@ pub struct Semantics<'db, DB> {
 pub struct SemanticsImpl<'db> {
     pub db: &'db dyn HirDatabase,
     s2d_cache: RefCell<SourceToDefCache>,
+    /// Definition to source pointer cache, used by [`SemanticsImpl::source`]
+    d2s_cache: DefToSrcCache,
     /// Rootnode to HirFileId cache
     root_to_file_cache: RefCell<FxHashMap<SyntaxNode, HirFileId>>,
     /// MacroCall to its expansion's MacroFileId cache
     macro_call_cache: RefCell<FxHashMap<InFile<ast::MacroCall>, MacroFileId>>,
+    /// Called for every child map dropped from `s2d_cache`, see [`SemanticsImpl::set_on_evict`].
+    on_evict: RefCell<Option<Rc<dyn Fn(&EvictedEntry)>>>,
+    /// See [`SemanticsImpl::set_resolve_in_macro_expansions`].
+    climb_out_of_macros: Cell<bool>,
 }
 
 impl<DB> fmt::Debug for Semantics<'_, DB> {
@@ -295,19 +488,22 @@ impl<'db> SemanticsImpl<'db> {
         SemanticsImpl {
             db,
             s2d_cache: Default::default(),
+            d2s_cache: Default::default(),
             root_to_file_cache: Default::default(),
             macro_call_cache: Default::default(),
+            on_evict: Default::default(),
+            climb_out_of_macros: Cell::new(true),
         }
     }
 
     pub fn parse(&self, file_id: FileId) -> ast::SourceFile {
-        let tree = self.db.parse(file_id).tree();
-        self.cache(tree.syntax().clone(), file_id.into());
-        tree
+        ast::SourceFile::cast(self.parse_or_expand(file_id.into())).unwrap()
     }
 
+    /// Parses or expands `file_id`, sharing the resulting tree with the sources handed out by
+    /// [`SemanticsImpl::source`].
     pub fn parse_or_expand(&self, file_id: HirFileId) -> SyntaxNode {
-        let node = self.db.parse_or_expand(file_id);
+        let node = self.d2s_cache.parse_or_expand(self.db.upcast(), file_id);
         self.cache(node.clone(), file_id);
         node
     }
@@ -402,12 +598,7 @@ impl<'db> SemanticsImpl<'db> {
         let res: Vec<_> = self
             .derive_macro_calls(attr)?
             .into_iter()
-            .flat_map(|call| {
-                let file_id = call?.as_file();
-                let node = self.db.parse_or_expand(file_id);
-                self.cache(node.clone(), file_id);
-                Some(node)
-            })
+            .flat_map(|call| Some(self.parse_or_expand(call?.as_file())))
             .collect();
         Some(res)
     }
@@ -429,6 +620,18 @@ impl<'db> SemanticsImpl<'db> {
         self.with_ctx(|ctx| ctx.has_derives(adt))
     }
 
+    /// Resolves the derives of all adts in `file` in one go, speeding up subsequent
+    /// [`Self::is_derive_annotated`] and derive macro call lookups in derive-heavy files.
+    pub fn prewarm_derives(&self, file: HirFileId) {
+        self.with_ctx(|ctx| ctx.prewarm_derives(file))
+    }
+
+    /// Builds the source-to-def data for the modules of `file` and the items declared in them up
+    /// front, speeding up subsequent lookups of many definitions in the file.
+    pub fn prewarm_file(&self, file: FileId) {
+        self.with_ctx(|ctx| ctx.prewarm_file(file))
+    }
+
     pub fn derive_helper(&self, attr: &ast::Attr) -> Option<Vec<(Macro, MacroFileId)>> {
         let adt = attr.syntax().ancestors().find_map(ast::Item::cast).and_then(|it| match it {
             ast::Item::Struct(it) => Some(ast::Adt::Struct(it)),
@@ -450,6 +653,14 @@ impl<'db> SemanticsImpl<'db> {
         res.is_empty().not().then_some(res)
     }
 
+    /// Resolves `item` to the attribute macro call it is the input of or, if there is none, to
+    /// the definition it declares. Derives don't replace the item, so a derived item resolves to
+    /// its definition.
+    pub fn resolve_or_expand(&self, item: &ast::Item) -> Option<ItemResolution> {
+        let src = self.wrap_node_infile(item.clone());
+        self.with_ctx(|ctx| ctx.resolve_or_expand(src.as_ref()))
+    }
+
     pub fn is_attr_macro_call(&self, item: &ast::Item) -> bool {
         let file_id = self.find_file(item.syntax()).file_id;
         let src = InFile::new(file_id, item);
@@ -577,6 +788,20 @@ impl<'db> SemanticsImpl<'db> {
         None
     }
 
+    /// Resolves the implicit capture at `offset` in the template of `format_args` to the local it
+    /// refers to. `offset` is relative to the start of the template string's contents. Captures
+    /// that don't name a local, e.g. constants, resolve to `None`.
+    pub fn resolve_format_args_capture(
+        &self,
+        format_args: &ast::FormatArgsExpr,
+        offset: TextSize,
+    ) -> Option<(TextRange, Local)> {
+        let src = self.wrap_node_infile(format_args.clone());
+        let (range, (parent, binding_id)) =
+            self.with_ctx(|ctx| ctx.format_args_capture_to_def(src.as_ref(), offset))?;
+        Some((range, Local { parent, binding_id }))
+    }
+
     fn resolve_offset_in_format_args(
         &self,
         string: ast::String,
@@ -763,25 +988,12 @@ impl<'db> SemanticsImpl<'db> {
             .filter(|&(_, include_file_id)| include_file_id == file_id)
         {
             let macro_file = invoc.as_macro_file();
-            let expansion_info = {
-                self.with_ctx(|ctx| {
-                    ctx.cache
-                        .expansion_info_cache
-                        .entry(macro_file)
-                        .or_insert_with(|| {
-                            let exp_info = macro_file.expansion_info(self.db.upcast());
-
-                            let InMacroFile { file_id, value } = exp_info.expanded();
-                            if let InFile { file_id, value: Some(value) } = exp_info.arg() {
-                                self.cache(value.ancestors().last().unwrap(), file_id);
-                            }
-                            self.cache(value, file_id.into());
-
-                            exp_info
-                        })
-                        .clone()
-                })
-            };
+            let expansion_info = self.with_ctx(|ctx| ctx.expansion_info(macro_file).clone());
+            let InMacroFile { file_id, value } = expansion_info.expanded();
+            if let InFile { file_id, value: Some(value) } = expansion_info.arg() {
+                self.cache(value.ancestors().last().unwrap(), file_id);
+            }
+            self.cache(value, file_id.into());
 
             // FIXME: uncached parse
             // Create the source analyzer for the macro call scope
@@ -852,21 +1064,10 @@ impl<'db> SemanticsImpl<'db> {
         let mut stack: Vec<(_, SmallVec<[_; 2]>)> = vec![(file_id, smallvec![token])];
         let process_expansion_for_token = |stack: &mut Vec<_>, macro_file| {
             let InMacroFile { file_id, value: mapped_tokens } = self.with_ctx(|ctx| {
-                Some(
-                    ctx.cache
-                        .expansion_info_cache
-                        .entry(macro_file)
-                        .or_insert_with(|| {
-                            let exp_info = macro_file.expansion_info(self.db.upcast());
-
-                            let InMacroFile { file_id, value } = exp_info.expanded();
-                            self.cache(value, file_id.into());
-
-                            exp_info
-                        })
-                        .map_range_down(span)?
-                        .map(SmallVec::<[_; 2]>::from_iter),
-                )
+                let exp_info = ctx.expansion_info(macro_file);
+                let InMacroFile { file_id, value } = exp_info.expanded();
+                self.cache(value, file_id.into());
+                Some(exp_info.map_range_down(span)?.map(SmallVec::<[_; 2]>::from_iter))
             })?;
 
             // we have found a mapping for the token if the vec is non-empty
@@ -1140,12 +1341,7 @@ impl<'db> SemanticsImpl<'db> {
                     let macro_file = file_id.macro_file()?;
 
                     self.with_ctx(|ctx| {
-                        let expansion_info = ctx
-                            .cache
-                            .expansion_info_cache
-                            .entry(macro_file)
-                            .or_insert_with(|| macro_file.expansion_info(self.db.upcast()));
-                        expansion_info.arg().map(|node| node?.parent()).transpose()
+                        ctx.expansion_info(macro_file).arg().map(|node| node?.parent()).transpose()
                     })
                 }
             }
@@ -1355,6 +1551,18 @@ impl<'db> SemanticsImpl<'db> {
         self.analyze(field.syntax())?.resolve_record_pat_field(self.db, field)
     }
 
+    /// Resolves a segment of the path of `macro_call`, like the `bar` in `foo::bar!()` to the
+    /// called macro and the `foo` to the module it is called through.
+    pub fn resolve_macro_call_path_segment(
+        &self,
+        macro_call: &ast::MacroCall,
+        segment: &ast::PathSegment,
+    ) -> Option<AnyDef> {
+        let call = self.find_file(macro_call.syntax()).with_value(macro_call);
+        let segment = call.with_value(segment);
+        self.with_ctx(|ctx| ctx.macro_call_path_segment_to_def(call, segment))
+    }
+
     pub fn resolve_macro_call(&self, macro_call: &ast::MacroCall) -> Option<Macro> {
         let macro_call = self.find_file(macro_call.syntax()).with_value(macro_call);
         self.with_ctx(|ctx| {
@@ -1418,6 +1626,102 @@ impl<'db> SemanticsImpl<'db> {
         self.analyze(pat.syntax())?.resolve_bind_pat_to_const(self.db, pat)
     }
 
+    /// Resolves the `as bar` of a (possibly nested) `use` tree to the item it imports.
+    pub fn resolve_use_rename(&self, rename: &ast::Rename) -> Option<PathResolution> {
+        let src = self.wrap_node_infile(rename.clone());
+        self.with_ctx(|ctx| ctx.use_rename_to_def(src.as_ref()))?;
+        let use_tree = ast::UseTree::cast(rename.syntax().parent()?)?;
+        self.resolve_path(&use_tree.path()?)
+    }
+
+    /// Resolves the path of an attribute, like the `inline` in `#[inline]`, to the builtin
+    /// attribute, attribute macro or tool it names.
+    pub fn resolve_attr_path(&self, attr: &ast::Attr) -> Option<AnyDef> {
+        let src = self.wrap_node_infile(attr.clone());
+        self.with_ctx(|ctx| ctx.attr_path_to_def(src.as_ref()))
+    }
+
+    /// Resolves a single leaf of a (possibly grouped) import, like the `c::d` in
+    /// `use a::{b, c::d};`, to the item it imports rather than to the `use` item.
+    pub fn resolve_use_tree_leaf(&self, use_tree: &ast::UseTree) -> Option<AnyDef> {
+        let src = self.wrap_node_infile(use_tree.clone());
+        self.with_ctx(|ctx| ctx.use_tree_leaf_to_def(src.as_ref()))
+    }
+
+    /// Resolves the explicit discriminant expression of a variant, like the `1` in
+    /// `enum E { A = 1 }`, to the variant's const body.
+    pub fn resolve_discriminant(&self, expr: &ast::Expr) -> Option<DefWithBody> {
+        let src = self.wrap_node_infile(expr.clone());
+        self.with_ctx(|ctx| ctx.discriminant_to_def(src.as_ref()))
+            .map(|it| Variant::from(it).into())
+    }
+
+    /// Resolves a block expression to the module holding the items declared in it. Only blocks
+    /// that contain items have such a module, for any other block this returns `None`.
+    pub fn block_module(&self, block: &ast::BlockExpr) -> Option<Module> {
+        let src = self.wrap_node_infile(block.clone());
+        self.with_ctx(|ctx| ctx.block_to_def(src.as_ref()))
+            .map(|block| self.db.block_def_map(block).root_module_id().into())
+    }
+
+    /// Resolves the `*` of a glob import like `use foo::bar::*;` to the module it imports from.
+    pub fn resolve_use_glob(&self, use_tree: &ast::UseTree) -> Option<Module> {
+        let src = self.wrap_node_infile(use_tree.clone());
+        self.with_ctx(|ctx| ctx.use_glob_to_def(src.as_ref())).map(Module::from)
+    }
+
+    /// Resolves a path type, like `Foo<Bar>` or the `Tr<u8>` in `dyn Tr<u8>`, to the definition
+    /// its base path refers to. Generic arguments don't take part in the resolution.
+    pub fn resolve_path_type(&self, ty: &ast::PathType) -> Option<AnyDef> {
+        AnyDef::from_path_resolution(self.resolve_path(&ty.path()?)?)
+    }
+
+    /// Resolves the name of a declaration, like the `Foo` in `struct Foo;`, to the definition it
+    /// declares.
+    pub fn name_to_def(&self, name: &ast::Name) -> Option<AnyDef> {
+        let src = self.wrap_node_infile(name.clone());
+        self.with_ctx(|ctx| ctx.name_to_def(src.as_ref()))
+    }
+
+    /// Resolves a name reference in a path, field access or method call to the definition it
+    /// refers to.
+    pub fn name_ref_to_def(&self, name_ref: &ast::NameRef) -> Option<AnyDef> {
+        let parent = name_ref.syntax().parent()?;
+        match_ast! {
+            match parent {
+                ast::PathSegment(segment) => {
+                    AnyDef::from_path_resolution(self.resolve_path(&segment.parent_path())?)
+                },
+                ast::FieldExpr(field) => match self.resolve_field(&field)? {
+                    Either::Left(field) => Some(field.into()),
+                    Either::Right(_) => None,
+                },
+                ast::MethodCallExpr(call) => Some(self.resolve_method_call(&call)?.into()),
+                _ => None,
+            }
+        }
+    }
+
+    /// Resolves an extern crate declaration to the crate it refers to.
+    pub fn resolve_extern_crate(&self, extern_crate: &ast::ExternCrate) -> Option<Crate> {
+        let src = self.wrap_node_infile(extern_crate.clone());
+        self.with_ctx(|ctx| {
+            let id = ctx.extern_crate_to_def(src.as_ref())?;
+            ctx.extern_crate_target(id)
+        })
+        .map(Crate::from)
+    }
+
+    /// Resolves an ident pattern either to the local it binds or, if it names a constant or unit
+    /// variant instead, to that definition.
+    pub fn resolve_ident_pat(&self, pat: &ast::IdentPat) -> Option<Either<Local, ModuleDef>> {
+        let src = self.wrap_node_infile(pat.clone());
+        match self.with_ctx(|ctx| ctx.ident_pat_to_def(src.as_ref()))? {
+            Either::Left((parent, binding_id)) => Some(Either::Left(Local { parent, binding_id })),
+            Either::Right(_) => self.resolve_bind_pat_to_const(pat).map(Either::Right),
+        }
+    }
+
     pub fn record_literal_missing_fields(&self, literal: &ast::RecordExpr) -> Vec<(Field, Type)> {
         self.analyze(literal.syntax())
             .and_then(|it| it.record_literal_missing_fields(self.db, literal))
@@ -1431,8 +1735,320 @@ impl<'db> SemanticsImpl<'db> {
     }
 
     fn with_ctx<F: FnOnce(&mut SourceToDefCtx<'_, '_>) -> T, T>(&self, f: F) -> T {
-        let mut ctx = SourceToDefCtx { db: self.db, cache: &mut self.s2d_cache.borrow_mut() };
-        f(&mut ctx)
+        let res = {
+            let mut ctx = SourceToDefCtx {
+                db: self.db,
+                cache: &mut self.s2d_cache.borrow_mut(),
+                d2s_cache: &self.d2s_cache,
+                climb_out_of_macros: self.climb_out_of_macros.get(),
+            };
+            f(&mut ctx)
+        };
+        let evicted = self.s2d_cache.borrow_mut().take_capacity_evictions();
+        self.notify_evicted(&evicted);
+        res
+    }
+
+    /// Resolves a method to the impl it is declared in, if any.
+    pub fn resolve_impl_for_method(&self, func: &ast::Fn) -> Option<Impl> {
+        let src = self.wrap_node_infile(func.clone());
+        self.with_ctx(|ctx| ctx.fn_to_impl(src.as_ref())).map(Impl::from)
+    }
+
+    /// Resolves the body of a trait or impl to its owning trait or impl.
+    pub fn assoc_item_list_owner(&self, list: &ast::AssocItemList) -> Option<AnyDef> {
+        let src = self.wrap_node_infile(list.clone());
+        self.with_ctx(|ctx| ctx.assoc_item_list_to_def(src.as_ref()))
+    }
+
+    /// Resolves a syntax node that declares a definition to that definition.
+    pub fn node_to_def(&self, node: &SyntaxNode) -> Option<AnyDef> {
+        let src = self.find_file(node);
+        self.with_ctx(|ctx| ctx.node_to_def(src))
+    }
+
+    /// Like [`Self::node_to_def`], but also returns the range of the definition's name, falling
+    /// back to the range of `node` for unnamed definitions like impls.
+    pub fn node_to_def_with_range(&self, node: &SyntaxNode) -> Option<(AnyDef, InFile<TextRange>)> {
+        let src = self.find_file(node);
+        self.with_ctx(|ctx| ctx.node_to_def_with_range(src))
+    }
+
+    /// Like [`Self::node_to_def`], but tells nodes that don't resolve because they are disabled by
+    /// a `#[cfg]` apart from nodes that don't declare anything.
+    pub fn node_to_def_outcome(&self, node: &SyntaxNode) -> ResolveOutcome {
+        let src = self.find_file(node);
+        self.with_ctx(|ctx| ctx.node_to_def_outcome(src))
+    }
+
+    /// Like [`Self::node_to_def`], but also resolves the node in the other crates its file is
+    /// part of.
+    pub fn node_to_def_detailed(&self, node: &SyntaxNode) -> Option<ResolveResult> {
+        let src = self.find_file(node);
+        self.with_ctx(|ctx| ctx.node_to_def_detailed(src))
+    }
+
+    /// Like [`Self::node_to_def`], but neither uses nor fills the caches of `self`. Use this for
+    /// speculative checks to avoid filling the cache with entries that won't be reused.
+    pub fn resolve_no_cache(&self, node: &SyntaxNode) -> Option<AnyDef> {
+        let src = self.find_file(node);
+        self.with_ctx(|ctx| ctx.resolve_no_cache(src))
+    }
+
+    /// Like [`Self::node_to_def`], but resolves a whole batch of nodes while sharing the
+    /// source-to-def caches between them.
+    pub fn nodes_to_defs<'a>(
+        &self,
+        nodes: impl IntoIterator<Item = &'a SyntaxNode>,
+    ) -> Vec<Option<AnyDef>> {
+        let nodes: Vec<_> = nodes.into_iter().map(|node| self.find_file(node)).collect();
+        self.with_ctx(|ctx| ctx.nodes_to_defs(nodes))
+    }
+
+    /// Resolves every name and name reference within `range` of `file` to the definition it
+    /// declares or refers to, as needed by selection-based features like "extract function".
+    /// Each definition is reported once, at its first occurrence. Tokens in macro calls are
+    /// resolved through the macro expansion.
+    pub fn resolve_all_in_range(&self, file: FileId, range: TextRange) -> Vec<(TextRange, AnyDef)> {
+        let root = self.parse(file);
+        let mut seen = FxHashSet::default();
+        let mut res = Vec::new();
+        let tokens = root
+            .syntax()
+            .descendants_with_tokens()
+            .filter_map(|it| it.into_token())
+            .filter(|it| range.contains_range(it.text_range()))
+            .filter(|it| matches!(it.kind(), SyntaxKind::IDENT | T![self] | T![Self]));
+        for token in tokens {
+            let range = token.text_range();
+            let token = self.descend_into_macros_single(DescendPreference::SameText, token);
+            if let Some(def) = self.resolve_name_like(&token) {
+                if seen.insert(def) {
+                    res.push((range, def));
+                }
+            }
+        }
+        res
+    }
+
+    fn resolve_name_like(&self, token: &SyntaxToken) -> Option<AnyDef> {
+        let parent = token.parent()?;
+        if let Some(name) = ast::Name::cast(parent.clone()) {
+            return self.node_to_def(&name.syntax().parent()?);
+        }
+        let parent = ast::NameRef::cast(parent)?.syntax().parent()?;
+        match_ast! {
+            match parent {
+                ast::PathSegment(it) => {
+                    AnyDef::from_path_resolution(self.resolve_path(&it.parent_path())?)
+                },
+                ast::FieldExpr(it) => self.resolve_field(&it)?.left().map(Into::into),
+                ast::MethodCallExpr(it) => self.resolve_method_call(&it).map(Into::into),
+                ast::RecordExprField(it) => {
+                    self.resolve_record_field(&it).map(|(field, ..)| field.into())
+                },
+                ast::RecordPatField(it) => {
+                    self.resolve_record_pat_field(&it).map(|(field, _)| field.into())
+                },
+                _ => None,
+            }
+        }
+    }
+
+    /// Resolves an enum variant to the [`VariantDef`] owning its fields.
+    pub fn to_variant_def(&self, variant: &ast::Variant) -> Option<VariantDef> {
+        let src = self.wrap_node_infile(variant.clone());
+        self.with_ctx(|ctx| ctx.variant_id_to_def(src.as_ref())).map(VariantDef::from)
+    }
+
+    /// Reports whether `token`, which must be part of a macro expansion, was written at the
+    /// macro's call site or stems from its definition.
+    ///
+    /// Returns `None` if `token` is not part of a macro expansion.
+    pub fn hygiene_origin(&self, token: &SyntaxToken) -> Option<HygieneOrigin> {
+        let parent = token.parent()?;
+        let file_id = self.find_file(&parent).file_id;
+        self.with_ctx(|ctx| ctx.hygiene_origin(InFile::new(file_id, token)))
+    }
+
+    /// Resolves the identifier `token` of a macro expansion in the scope it was written in, as
+    /// reported by [`Self::hygiene_origin`]: identifiers passed in by the caller resolve at the
+    /// macro call, the ones written in the macro itself at the macro definition.
+    ///
+    /// Returns `None` if `token` isn't a name reference in a macro expansion or doesn't resolve.
+    pub fn resolve_hygienic(&self, token: &SyntaxToken) -> Option<(HygieneOrigin, PathResolution)> {
+        let name_ref = token.parent().and_then(ast::NameRef::cast)?;
+        let origin = self.hygiene_origin(token)?;
+        let macro_file = self.find_file(name_ref.syntax()).file_id.macro_file()?;
+        let loc = self.db.lookup_intern_macro_call(macro_file.macro_call_id);
+        let site = match origin {
+            HygieneOrigin::CallSite => loc.to_node(self.db.upcast()),
+            HygieneOrigin::DefSite => match loc.def.kind {
+                hir_expand::MacroDefKind::Declarative(id)
+                | hir_expand::MacroDefKind::BuiltIn(id, _)
+                | hir_expand::MacroDefKind::BuiltInAttr(id, _)
+                | hir_expand::MacroDefKind::BuiltInDerive(id, _)
+                | hir_expand::MacroDefKind::BuiltInEager(id, _) => {
+                    id.to_in_file_node(self.db.upcast()).map(|it| it.syntax().clone())
+                }
+                // The definition site of a proc-macro is in another crate, which the expansion
+                // can't refer to.
+                hir_expand::MacroDefKind::ProcMacro(..) => return None,
+            },
+        };
+        // The node comes from the database, look it up in the tree known to `self`.
+        let root = self.parse_or_expand(site.file_id);
+        let site = SyntaxNodePtr::new(&site.value).to_node(&root);
+        let scope = self.scope(&site)?;
+        let path = Path::from_known_path_with_no_generic(ModPath::from_segments(
+            PathKind::Plain,
+            Some(name_ref.as_name()),
+        ));
+        let res = resolve_hir_path(self.db, &scope.resolver, &path)?;
+        Some((origin, res))
+    }
+
+    /// Resolves `token` to the narrowest definition enclosing it.
+    pub fn token_to_def(&self, token: &SyntaxToken) -> Option<AnyDef> {
+        let parent = token.parent()?;
+        let file_id = self.find_file(&parent).file_id;
+        self.with_ctx(|ctx| ctx.token_to_def(InFile::new(file_id, token)))
+    }
+
+    /// Returns the expansion of the macro file `file_id`, registering it with this `Semantics` so
+    /// that definitions inside of it can be resolved.
+    pub fn expand_and_map(&self, file_id: MacroFileId) -> InMacroFile<SyntaxNode> {
+        let expanded = self.with_ctx(|ctx| ctx.expand_and_map(file_id.macro_call_id).expanded());
+        self.cache(expanded.value.clone(), file_id.into());
+        expanded
+    }
+
+    /// Returns the [`ExpansionInfo`] of the macro file `file_id`, computing it only if this
+    /// `Semantics` hasn't done so yet. Like [`Self::expand_and_map`], the expansion is registered
+    /// so that its nodes can be resolved.
+    pub fn expansion_info(&self, file_id: MacroFileId) -> ExpansionInfo {
+        let info = self.with_ctx(|ctx| ctx.expansion_info(file_id).clone());
+        self.cache(info.expanded().value, file_id.into());
+        info
+    }
+
+    /// Returns the file the macro call `call` expands to. The expansion is computed only once per
+    /// `Semantics`, and [`Self::parse_or_expand`] of the returned file hands out its tree.
+    pub fn macro_call_to_expanded_file(&self, call: MacroCallId) -> MacroFileId {
+        self.with_ctx(|ctx| ctx.macro_call_to_expanded_file(call))
+    }
+
+    /// Resolves the node `ptr` points to in the expansion of the macro file `file_id` to the
+    /// definition it declares.
+    pub fn expansion_node_to_def(
+        &self,
+        file_id: MacroFileId,
+        ptr: SyntaxNodePtr,
+    ) -> Option<AnyDef> {
+        self.with_ctx(|ctx| ctx.expansion_node_to_def(file_id.macro_call_id, ptr))
+    }
+
+    /// Returns the const parameter whose default value contains `expr`, if any.
+    pub fn const_param_default_owner(&self, expr: &ast::Expr) -> Option<ConstParam> {
+        let src = self.wrap_node_infile(expr.clone());
+        self.with_ctx(|ctx| ctx.const_param_default_to_def(src.as_ref())).map(ConstParam::from)
+    }
+
+    /// Returns the item whose where clause has `expr` as a const operand, like the `N` in
+    /// `where [(); N]: Sized`.
+    pub fn where_clause_const_owner(&self, expr: &ast::Expr) -> Option<GenericDef> {
+        let src = self.wrap_node_infile(expr.clone());
+        self.with_ctx(|ctx| ctx.where_clause_const_to_def(src.as_ref())).map(GenericDef::from)
+    }
+
+    /// Resolves the intra-doc link `link` in the scope of the item documented by the doc
+    /// attribute or doc comment owned by `node`.
+    pub fn resolve_doc_link(&self, node: &SyntaxNode, link: &str) -> Option<AnyDef> {
+        let src = self.find_file(node);
+        self.with_ctx(|ctx| ctx.doc_link_to_def(src, link))
+    }
+
+    /// Drops the source-to-def data and the syntax tree cached for `file_id`, so that subsequent
+    /// lookups rebuild them.
+    pub fn invalidate_file(&self, file_id: HirFileId) {
+        let evicted = self.s2d_cache.borrow_mut().invalidate_file(file_id);
+        self.d2s_cache.invalidate_file(file_id);
+        self.notify_evicted(&evicted);
+    }
+
+    /// Drops all cached source-to-def and def-to-source data, e.g. after the workspace was
+    /// reloaded.
+    pub fn invalidate_all(&self) {
+        let evicted = self.s2d_cache.borrow_mut().invalidate_all();
+        self.d2s_cache.clear();
+        self.notify_evicted(&evicted);
+    }
+
+    /// Sets whether nodes inside of macro expansions resolve in the context of their macro call,
+    /// which is the default. If disabled, source-to-def lookups treat macro calls as opaque and
+    /// stop at the call instead of climbing out of the expansion.
+    pub fn set_resolve_in_macro_expansions(&self, enabled: bool) {
+        if self.climb_out_of_macros.replace(enabled) != enabled {
+            self.s2d_cache.borrow_mut().invalidate_containers();
+        }
+    }
+
+    /// Bounds the number of source-to-def child maps kept cached to `capacity`. Once it is
+    /// exceeded, the least recently used maps are dropped. `None`, the default, lifts the bound.
+    pub fn set_source_to_def_capacity(&self, capacity: Option<usize>) {
+        let evicted = {
+            let mut cache = self.s2d_cache.borrow_mut();
+            cache.set_capacity(capacity);
+            cache.take_capacity_evictions()
+        };
+        self.notify_evicted(&evicted);
+    }
+
+    /// Registers a callback that is called for every source-to-def child map dropped by an
+    /// invalidation or to stay within the capacity set by
+    /// [`SemanticsImpl::set_source_to_def_capacity`], e.g. to keep an external index of the
+    /// cache up to date. Replaces any previously registered callback.
+    ///
+    /// The callback runs after the cache has been updated and isn't borrowed anymore, so it may
+    /// query `self`, and even replace itself.
+    pub fn set_on_evict(&self, on_evict: impl Fn(&EvictedEntry) + 'static) {
+        *self.on_evict.borrow_mut() = Some(Rc::new(on_evict));
+    }
+
+    fn notify_evicted(&self, evicted: &[EvictedEntry]) {
+        if evicted.is_empty() {
+            return;
+        }
+        // Don't hold the borrow while the callback runs, it may call `set_on_evict`.
+        let Some(on_evict) = self.on_evict.borrow().clone() else { return };
+        for entry in evicted {
+            on_evict(entry);
+        }
+    }
+
+    /// Returns a counter that advances whenever source-to-def data gets invalidated, so that
+    /// results derived from it can be checked for staleness.
+    pub fn source_to_def_epoch(&self) -> u64 {
+        self.s2d_cache.borrow().epoch
+    }
+
+    /// Returns how often source-to-def child maps had to be rebuilt after being invalidated.
+    pub fn source_to_def_rebuild_count(&self) -> usize {
+        self.s2d_cache.borrow().dynmap_rebuilds
+    }
+
+    /// Returns how often source-to-def lookups could reuse an already built child map, and how
+    /// many macro expansions they had to compute.
+    pub fn source_to_def_stats(&self) -> CacheStats {
+        self.s2d_cache.borrow().stats
+    }
+
+    /// Dumps the source-to-def map of the container `node` is resolved in, for debugging
+    /// definitions that fail to resolve.
+    #[doc(hidden)]
+    pub fn dump_source_to_def_container(&self, node: &SyntaxNode) -> Option<String> {
+        let src = self.find_file(node);
+        self.with_ctx(|ctx| ctx.dump_container(src))
     }
 
     pub fn to_def<T: ToDef>(&self, src: &T) -> Option<T::Def> {
@@ -1441,7 +2057,7 @@ impl<'db> SemanticsImpl<'db> {
     }
 
     fn file_to_module_defs(&self, file: FileId) -> impl Iterator<Item = Module> {
-        self.with_ctx(|ctx| ctx.file_to_def(file).to_owned()).into_iter().map(Module::from)
+        self.with_ctx(|ctx| ctx.file_to_def_cloned(file)).into_iter().map(Module::from)
     }
 
     pub fn scope(&self, node: &SyntaxNode) -> Option<SemanticsScope<'db>> {
@@ -1466,13 +2082,14 @@ impl<'db> SemanticsImpl<'db> {
         )
     }
 
-    /// Search for a definition's source and cache its syntax tree
+    /// Search for a definition's source and cache its syntax tree.
+    ///
+    /// Definitions of the same file share a single parse of it for the lifetime of `self`.
     pub fn source<Def: HasSource>(&self, def: Def) -> Option<InFile<Def::Ast>>
     where
         Def::Ast: AstNode,
     {
-        // FIXME: source call should go through the parse cache
-        let res = def.source(self.db)?;
+        let res = def.source_with_ctx(self.db, &self.d2s_cache)?;
         self.cache(find_root(res.value.syntax()), res.file_id);
         Some(res)
     }
@@ -1517,6 +2134,7 @@ impl<'db> SemanticsImpl<'db> {
             ChildContainer::TraitId(it) => it.resolver(self.db.upcast()),
             ChildContainer::TraitAliasId(it) => it.resolver(self.db.upcast()),
             ChildContainer::ImplId(it) => it.resolver(self.db.upcast()),
+            ChildContainer::ExternBlockId(it) => it.resolver(self.db.upcast()),
             ChildContainer::ModuleId(it) => it.resolver(self.db.upcast()),
             ChildContainer::EnumId(it) => it.resolver(self.db.upcast()),
             ChildContainer::VariantId(it) => it.resolver(self.db.upcast()),
@@ -1719,11 +2337,7 @@ fn macro_call_to_macro_id(
                     it.to_ptr(db).to_node(&db.parse(file_id).syntax_node())
                 }
                 HirFileIdRepr::MacroFile(macro_file) => {
-                    let expansion_info = ctx
-                        .cache
-                        .expansion_info_cache
-                        .entry(macro_file)
-                        .or_insert_with(|| macro_file.expansion_info(ctx.db.upcast()));
+                    let expansion_info = ctx.expand_and_map(macro_file.macro_call_id);
                     it.to_ptr(db).to_node(&expansion_info.expanded().value)
                 }
             };
@@ -1735,11 +2349,7 @@ fn macro_call_to_macro_id(
                     it.to_ptr(db).to_node(&db.parse(file_id).syntax_node())
                 }
                 HirFileIdRepr::MacroFile(macro_file) => {
-                    let expansion_info = ctx
-                        .cache
-                        .expansion_info_cache
-                        .entry(macro_file)
-                        .or_insert_with(|| macro_file.expansion_info(ctx.db.upcast()));
+                    let expansion_info = ctx.expansion_info(macro_file);
                     it.to_ptr(db).to_node(&expansion_info.expanded().value)
                 }
             };
@@ -1777,11 +2387,16 @@ to_def_impls![
     (crate::Const, ast::Const, const_to_def),
     (crate::Static, ast::Static, static_to_def),
     (crate::Function, ast::Fn, fn_to_def),
+    (crate::Function, ast::MethodCallExpr, method_call_to_def),
     (crate::Field, ast::RecordField, record_field_to_def),
     (crate::Field, ast::TupleField, tuple_field_to_def),
+    (crate::Field, ast::RecordExprField, record_expr_field_to_def),
+    (crate::Field, ast::RecordPatField, record_pat_field_to_def),
     (crate::Variant, ast::Variant, enum_variant_to_def),
     (crate::TypeParam, ast::TypeParam, type_param_to_def),
+    (crate::TypeParam, ast::ImplTraitType, impl_trait_type_to_def),
     (crate::LifetimeParam, ast::LifetimeParam, lifetime_param_to_def),
+    (crate::LifetimeParam, ast::Lifetime, lifetime_to_def),
     (crate::ConstParam, ast::ConstParam, const_param_to_def),
     (crate::GenericParam, ast::GenericParam, generic_param_to_def),
     (crate::Macro, ast::Macro, macro_to_def),
@@ -1789,10 +2404,23 @@ to_def_impls![
     (crate::Local, ast::SelfParam, self_param_to_def),
     (crate::Label, ast::Label, label_to_def),
     (crate::Adt, ast::Adt, adt_to_def),
+    (crate::ExternBlock, ast::ExternBlock, extern_block_to_def),
     (crate::ExternCrateDecl, ast::ExternCrate, extern_crate_to_def),
+    (crate::ExternCrateDecl, ast::Rename, extern_crate_alias_to_def),
     (MacroCallId, ast::MacroCall, macro_call_to_macro_call),
 ];
 
+impl ToDef for FieldSource {
+    type Def = Field;
+    fn to_def(sema: &SemanticsImpl<'_>, src: InFile<&Self>) -> Option<Self::Def> {
+        let field = match src.value {
+            FieldSource::Pos(it) => Either::Left(it),
+            FieldSource::Named(it) => Either::Right(it),
+        };
+        sema.with_ctx(|ctx| ctx.field_to_def(src.with_value(field))).map(Field::from)
+    }
+}
+
 fn find_root(node: &SyntaxNode) -> SyntaxNode {
     node.ancestors().last().unwrap()
 }
@@ -85,7 +85,13 @@
 //! active crate for a given position, and then provide an API to resolve all
 //! syntax nodes against this specific crate.
 
-use base_db::FileId;
+use std::{
+    any::TypeId,
+    collections::{hash_map::Entry, BTreeMap, VecDeque},
+    mem,
+};
+
+use base_db::{CrateId, FileId};
 use either::Either;
 use hir_def::{
     child_by_source::ChildBySource,
@@ -93,35 +99,235 @@ use hir_def::{
         keys::{self, Key},
         DynMap,
     },
-    hir::{BindingId, LabelId},
-    AdtId, BlockId, ConstId, ConstParamId, DefWithBodyId, EnumId, EnumVariantId, ExternCrateId,
-    FieldId, FunctionId, GenericDefId, GenericParamId, ImplId, LifetimeParamId, MacroId, ModuleId,
-    StaticId, StructId, TraitAliasId, TraitId, TypeAliasId, TypeParamId, UnionId, UseId, VariantId,
+    generics::TypeParamProvenance,
+    hir::{BindingId, LabelId, PatId},
+    item_tree::ImportKind,
+    nameres::{MacroSubNs, ModuleOrigin},
+    resolver::{HasResolver, LifetimeNs, Resolver},
+    src::{DefToSrcCache, HasChildSource},
+    type_ref::LifetimeRef,
+    AdtId, BlockId, ConstId, ConstParamId, DefWithBodyId, EnumId, EnumVariantId, ExternBlockId,
+    ExternCrateId, FieldId, FunctionId, GenericDefId, GenericParamId, ImplId, LifetimeParamId,
+    Lookup, MacroId, ModuleDefId, ModuleId, StaticId, StructId, TraitAliasId, TraitId, TypeAliasId,
+    TypeOrConstParamId, TypeParamId, UnionId, UseId, VariantId,
 };
 use hir_expand::{
-    attrs::AttrId, name::AsName, ExpansionInfo, HirFileId, HirFileIdExt, MacroCallId,
+    attrs::{AttrId, RawAttrs},
+    mod_path::ModPath,
+    name::{AsName, Name},
+    ExpansionInfo, HirFileId, HirFileIdExt, MacroCallId,
 };
-use rustc_hash::FxHashMap;
-use smallvec::SmallVec;
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::{smallvec, SmallVec};
 use span::MacroFileId;
 use stdx::impl_from;
 use syntax::{
-    ast::{self, HasName},
-    AstNode, AstPtr, SyntaxNode,
+    ast::{self, HasAttrs as _, HasName},
+    match_ast, AstNode, AstPtr, SyntaxKind, SyntaxNode, SyntaxNodePtr, SyntaxToken, TextRange,
+    TextSize,
 };
 
-use crate::{db::HirDatabase, InFile};
+use crate::{
+    attrs::resolve_doc_link_on_any_def,
+    db::HirDatabase,
+    semantics::{
+        AnyDef, CacheStats, EvictedEntry, HygieneOrigin, ItemResolution, ResolveOutcome,
+        ResolveResult,
+    },
+    BuiltinAttr, Const, ConstParam, Crate, Enum, ExternCrateDecl, Field, Function, Impl, InFile,
+    Label, LifetimeParam, Local, Macro, Module, Static, Struct, ToolModule, Trait, TraitAlias,
+    TypeAlias, TypeParam, Union, Variant,
+};
 
 #[derive(Default)]
 pub(super) struct SourceToDefCache {
     pub(super) dynmap_cache: FxHashMap<(ChildContainer, HirFileId), DynMap>,
     pub(super) expansion_info_cache: FxHashMap<MacroFileId, ExpansionInfo>,
     pub(super) file_to_def_cache: FxHashMap<FileId, SmallVec<[ModuleId; 1]>>,
+    /// Keys of child maps that were dropped from `dynmap_cache` and haven't been rebuilt since.
+    /// Only the last [`EVICTED_DYNMAPS_LIMIT`] are remembered.
+    evicted_dynmaps: FxHashSet<(ChildContainer, HirFileId)>,
+    /// The keys added to `evicted_dynmaps`, oldest first.
+    evicted_order: VecDeque<(ChildContainer, HirFileId)>,
+    /// How often a previously dropped child map had to be rebuilt.
+    pub(super) dynmap_rebuilds: usize,
+    /// How often `dynmap_cache` could, or could not, serve a child map lookup.
+    pub(super) stats: CacheStats,
+    /// `to_def` lookups that are known to not resolve to anything, keyed by the type of the key
+    /// they were looked up with.
+    to_def_misses: FxHashSet<(TypeId, SyntaxNodePtr, HirFileId)>,
+    /// Results of [`SourceToDefCtx::find_container`], keyed by the node the search started at.
+    container_cache: FxHashMap<(SyntaxNodePtr, HirFileId), Option<ChildContainer>>,
+    /// Module declarations currently being resolved by [`SourceToDefCtx::module_to_def`].
+    modules_in_progress: FxHashSet<InFile<AstPtr<ast::Module>>>,
+    /// Bumped on every invalidation, so that holders of data derived from this cache can tell
+    /// whether it may be stale.
+    pub(super) epoch: u64,
+    /// Served for containers queried in a file none of their children can be declared in, see
+    /// [`ChildContainer::fixed_file`].
+    empty_dynmap: DynMap,
+    /// The use order of the entries of `dynmap_cache`, if its size is bounded.
+    dynmap_lru: Option<DynMapLru>,
+    /// Child maps dropped to make room for others since the last call to
+    /// [`SourceToDefCache::take_capacity_evictions`].
+    capacity_evictions: Vec<EvictedEntry>,
+}
+
+/// How many dropped child maps [`SourceToDefCache`] remembers to tell whether a child map is
+/// being rebuilt.
+const EVICTED_DYNMAPS_LIMIT: usize = 1024;
+
+/// Tracks which child maps were used least recently, to pick the ones to drop once
+/// [`SourceToDefCache::dynmap_cache`] is full.
+struct DynMapLru {
+    capacity: usize,
+    tick: u64,
+    last_used: FxHashMap<(ChildContainer, HirFileId), u64>,
+    by_last_use: BTreeMap<u64, (ChildContainer, HirFileId)>,
+}
+
+impl DynMapLru {
+    fn touch(&mut self, key: (ChildContainer, HirFileId)) {
+        self.tick += 1;
+        if let Some(prev) = self.last_used.insert(key, self.tick) {
+            self.by_last_use.remove(&prev);
+        }
+        self.by_last_use.insert(self.tick, key);
+    }
+
+    fn remove(&mut self, key: &(ChildContainer, HirFileId)) {
+        if let Some(prev) = self.last_used.remove(key) {
+            self.by_last_use.remove(&prev);
+        }
+    }
+
+    fn pop_least_recently_used(&mut self) -> Option<(ChildContainer, HirFileId)> {
+        let (_, key) = self.by_last_use.pop_first()?;
+        self.last_used.remove(&key);
+        Some(key)
+    }
+}
+
+impl SourceToDefCache {
+    /// Bounds the number of cached child maps to `capacity`, dropping the least recently used
+    /// ones once it is exceeded. `None` lifts the bound.
+    pub(super) fn set_capacity(&mut self, capacity: Option<usize>) {
+        let Some(capacity) = capacity.map(|it| it.max(1)) else {
+            self.dynmap_lru = None;
+            return;
+        };
+        let dynmap_cache = &self.dynmap_cache;
+        let lru = self.dynmap_lru.get_or_insert_with(|| {
+            let mut lru = DynMapLru {
+                capacity,
+                tick: 0,
+                last_used: FxHashMap::default(),
+                by_last_use: BTreeMap::new(),
+            };
+            // The use order of the maps cached so far isn't known, treat them all as old.
+            dynmap_cache.keys().for_each(|&key| lru.touch(key));
+            lru
+        });
+        lru.capacity = capacity;
+        self.shrink_to(capacity);
+    }
+
+    /// Drops the least recently used child maps until at most `len` are left.
+    fn shrink_to(&mut self, len: usize) {
+        let Some(lru) = &mut self.dynmap_lru else { return };
+        while self.dynmap_cache.len() > len {
+            let Some(key) = lru.pop_least_recently_used() else { break };
+            if self.dynmap_cache.remove(&key).is_some() {
+                self.record_evicted(key);
+                self.capacity_evictions.push(EvictedEntry { file_id: key.1 });
+            }
+        }
+    }
+
+    /// Remembers that the child map for `key` was dropped, forgetting about the oldest dropped
+    /// map if there are too many.
+    fn record_evicted(&mut self, key: (ChildContainer, HirFileId)) {
+        if !self.evicted_dynmaps.insert(key) {
+            return;
+        }
+        self.evicted_order.push_back(key);
+        if self.evicted_order.len() > EVICTED_DYNMAPS_LIMIT {
+            if let Some(oldest) = self.evicted_order.pop_front() {
+                self.evicted_dynmaps.remove(&oldest);
+            }
+        }
+    }
+
+    /// Returns the child maps dropped because the cache was full since the last call.
+    pub(super) fn take_capacity_evictions(&mut self) -> Vec<EvictedEntry> {
+        mem::take(&mut self.capacity_evictions)
+    }
+
+    /// Drops all cached child maps that were built for `file`, as well as the expansion info of
+    /// `file` if it is a macro file.
+    /// Returns the dropped child maps.
+    pub(super) fn invalidate_file(&mut self, file: HirFileId) -> Vec<EvictedEntry> {
+        let mut dropped = Vec::new();
+        let lru = &mut self.dynmap_lru;
+        self.dynmap_cache.retain(|&key, _| {
+            let keep = key.1 != file;
+            if !keep {
+                if let Some(lru) = lru {
+                    lru.remove(&key);
+                }
+                dropped.push(key);
+            }
+            keep
+        });
+        dropped.iter().for_each(|&key| self.record_evicted(key));
+        self.to_def_misses.retain(|(_, _, file_id)| *file_id != file);
+        self.container_cache.retain(|(_, file_id), _| *file_id != file);
+        if let Some(macro_file) = file.macro_file() {
+            self.expansion_info_cache.remove(&macro_file);
+        }
+        self.epoch += 1;
+        dropped.into_iter().map(|(_, file_id)| EvictedEntry { file_id }).collect()
+    }
+
+    /// Drops the containers found for nodes and the lookups known to miss, which both depend on
+    /// how [`SourceToDefCtx::ancestors_with_macros`] treats macro calls.
+    pub(super) fn invalidate_containers(&mut self) {
+        self.to_def_misses.clear();
+        self.container_cache.clear();
+        self.epoch += 1;
+    }
+
+    /// Drops everything cached, e.g. after the workspace was reloaded. A [`SourceToDefCtx`]
+    /// created afterwards starts out with a fresh cache. Returns the dropped child maps.
+    pub(super) fn invalidate_all(&mut self) -> Vec<EvictedEntry> {
+        let dropped: Vec<_> = self.dynmap_cache.drain().map(|(key, _)| key).collect();
+        // Start counting rebuilds afresh, only the maps dropped now can be rebuilt.
+        self.evicted_dynmaps.clear();
+        self.evicted_order.clear();
+        dropped.iter().for_each(|&key| self.record_evicted(key));
+        if let Some(lru) = &mut self.dynmap_lru {
+            lru.last_used.clear();
+            lru.by_last_use.clear();
+        }
+        self.expansion_info_cache.clear();
+        self.file_to_def_cache.clear();
+        self.to_def_misses.clear();
+        self.container_cache.clear();
+        self.epoch += 1;
+        dropped.into_iter().map(|(_, file_id)| EvictedEntry { file_id }).collect()
+    }
 }
 
 pub(super) struct SourceToDefCtx<'db, 'cache> {
     pub(super) db: &'db dyn HirDatabase,
     pub(super) cache: &'cache mut SourceToDefCache,
+    /// The def-to-src cache of the same [`crate::Semantics`], which shares the trees of the macro
+    /// expansions computed here.
+    pub(super) d2s_cache: &'cache DefToSrcCache,
+    /// Whether nodes in a macro expansion resolve in the context of the macro call, climbing out
+    /// of the expansion. If not, macro calls are treated as opaque, see
+    /// [`Self::ancestors_with_macros`].
+    pub(super) climb_out_of_macros: bool,
 }
 
 impl SourceToDefCtx<'_, '_> {
@@ -145,11 +351,35 @@ impl SourceToDefCtx<'_, '_> {
         })
     }
 
+    /// Like [`Self::file_to_def`], but returns an owned copy for callers that need to keep using
+    /// `self` while holding on to the result.
+    pub(super) fn file_to_def_cloned(&mut self, file: FileId) -> SmallVec<[ModuleId; 1]> {
+        self.file_to_def(file).clone()
+    }
+
     pub(super) fn module_to_def(&mut self, src: InFile<&ast::Module>) -> Option<ModuleId> {
         let _p = tracing::info_span!("module_to_def").entered();
+        // Resolving a module resolves its parent declaration first. That recursion only visits
+        // syntactic ancestors, but make sure a malformed (e.g. cyclic `#[path]`) setup can't send
+        // us around in circles.
+        let key = src.map(AstPtr::new);
+        if !self.cache.modules_in_progress.insert(key.clone()) {
+            return None;
+        }
+        let res = self.module_to_def_(src);
+        self.cache.modules_in_progress.remove(&key);
+        res
+    }
+
+    fn module_to_def_(&mut self, src: InFile<&ast::Module>) -> Option<ModuleId> {
         let parent_declaration = self
-            .ancestors_with_macros(src.syntax_ref(), |_, ancestor| {
-                ancestor.map(Either::<ast::Module, ast::BlockExpr>::cast).transpose()
+            .ancestors_with_macros(src.syntax_ref(), |this, ancestor| {
+                let it = ancestor.map(Either::<ast::Module, ast::BlockExpr>::cast).transpose()?;
+                match &it.value {
+                    // blocks without items have no block module, so keep climbing
+                    Either::Right(block) if !this.block_has_def(it.with_value(block)) => None,
+                    _ => Some(it),
+                }
             })
             .map(|it| it.transpose());
 
@@ -193,6 +423,15 @@ impl SourceToDefCtx<'_, '_> {
     pub(super) fn fn_to_def(&mut self, src: InFile<&ast::Fn>) -> Option<FunctionId> {
         self.to_def(src, keys::FUNCTION)
     }
+    /// Resolves a method to the impl it is declared in. Free functions and functions declared in
+    /// traits have no impl.
+    pub(super) fn fn_to_impl(&mut self, src: InFile<&ast::Fn>) -> Option<ImplId> {
+        self.fn_to_def(src)?;
+        match self.find_container(src.syntax_ref())? {
+            ChildContainer::ImplId(id) => Some(id),
+            _ => None,
+        }
+    }
     pub(super) fn struct_to_def(&mut self, src: InFile<&ast::Struct>) -> Option<StructId> {
         self.to_def(src, keys::STRUCT)
     }
@@ -223,25 +462,167 @@ impl SourceToDefCtx<'_, '_> {
     pub(super) fn tuple_field_to_def(&mut self, src: InFile<&ast::TupleField>) -> Option<FieldId> {
         self.to_def(src, keys::TUPLE_FIELD)
     }
+    pub(super) fn field_to_def(
+        &mut self,
+        InFile { file_id, value }: InFile<Either<&ast::TupleField, &ast::RecordField>>,
+    ) -> Option<FieldId> {
+        match value {
+            Either::Left(it) => self.tuple_field_to_def(InFile::new(file_id, it)),
+            Either::Right(it) => self.record_field_to_def(InFile::new(file_id, it)),
+        }
+    }
+    pub(super) fn record_expr_field_to_def(
+        &mut self,
+        src: InFile<&ast::RecordExprField>,
+    ) -> Option<FieldId> {
+        let container = self.find_pat_or_label_container(src.syntax_ref())?;
+        let record_expr = src.value.syntax().ancestors().find_map(ast::RecordExpr::cast)?;
+        let (_body, source_map) = self.db.body_with_source_map(container);
+        let expr_id = source_map.node_expr(src.with_value(&ast::Expr::from(record_expr)))?;
+        let variant = self.db.infer(container).variant_resolution_for_expr(expr_id)?;
+        // `field_name` also handles the shorthand `S { x }` form
+        let field_name = src.value.field_name()?.as_name();
+        let local_id = variant.variant_data(self.db.upcast()).field(&field_name)?;
+        Some(FieldId { parent: variant, local_id })
+    }
+    pub(super) fn record_pat_field_to_def(
+        &mut self,
+        src: InFile<&ast::RecordPatField>,
+    ) -> Option<FieldId> {
+        let container = self.find_pat_or_label_container(src.syntax_ref())?;
+        let record_pat = src.value.parent_record_pat();
+        let (_body, source_map) = self.db.body_with_source_map(container);
+        let pat_id = source_map.node_pat(src.with_value(&ast::Pat::from(record_pat)))?;
+        let variant = self.db.infer(container).variant_resolution_for_pat(pat_id)?;
+        // `field_name` also handles the shorthand `S { x }` form
+        let field_name = src.value.field_name()?.as_name();
+        let local_id = variant.variant_data(self.db.upcast()).field(&field_name)?;
+        Some(FieldId { parent: variant, local_id })
+    }
+    /// Note that only blocks that contain items have a [`BlockId`], for any other block this
+    /// returns `None`. Use [`Self::block_has_def`] to check for that case.
     pub(super) fn block_to_def(&mut self, src: InFile<&ast::BlockExpr>) -> Option<BlockId> {
         self.to_def(src, keys::BLOCK)
     }
+    /// Whether the block introduces a block module, that is whether it contains any items.
+    pub(super) fn block_has_def(&mut self, src: InFile<&ast::BlockExpr>) -> bool {
+        self.block_to_def(src).is_some()
+    }
     pub(super) fn enum_variant_to_def(
         &mut self,
         src: InFile<&ast::Variant>,
     ) -> Option<EnumVariantId> {
         self.to_def(src, keys::ENUM_VARIANT)
     }
+    /// Like [`Self::enum_variant_to_def`], but returns the variant as the [`VariantId`] owning
+    /// its fields.
+    pub(super) fn variant_id_to_def(&mut self, src: InFile<&ast::Variant>) -> Option<VariantId> {
+        self.enum_variant_to_def(src).map(VariantId::EnumVariantId)
+    }
+    /// Resolves the explicit discriminant expression of a variant, like the `1` in
+    /// `enum E { A = 1 }`, to the variant whose const body it is.
+    pub(super) fn discriminant_to_def(&mut self, src: InFile<&ast::Expr>) -> Option<EnumVariantId> {
+        let variant = ast::Variant::cast(src.value.syntax().parent()?)?;
+        if variant.expr().as_ref() != Some(src.value) {
+            return None;
+        }
+        self.enum_variant_to_def(src.with_value(&variant))
+    }
     pub(super) fn extern_crate_to_def(
         &mut self,
         src: InFile<&ast::ExternCrate>,
     ) -> Option<ExternCrateId> {
         self.to_def(src, keys::EXTERN_CRATE)
     }
-    #[allow(dead_code)]
+    /// Resolves the `as bar` of an `extern crate foo as bar;` to the extern crate declaration it
+    /// renames. Renames of `use` trees aren't extern crate aliases and resolve to `None`.
+    pub(super) fn extern_crate_alias_to_def(
+        &mut self,
+        src: InFile<&ast::Rename>,
+    ) -> Option<ExternCrateId> {
+        let extern_crate = ast::ExternCrate::cast(src.value.syntax().parent()?)?;
+        self.extern_crate_to_def(src.with_value(&extern_crate))
+    }
+    /// Returns the crate an extern crate declaration refers to, as found in the extern prelude of
+    /// the declaring crate. `extern crate self as foo;` refers to the declaring crate itself.
+    pub(super) fn extern_crate_target(&mut self, id: ExternCrateId) -> Option<CrateId> {
+        self.db.extern_crate_decl_data(id).crate_id
+    }
     pub(super) fn use_to_def(&mut self, src: InFile<&ast::Use>) -> Option<UseId> {
         self.to_def(src, keys::USE)
     }
+    /// Resolves the `as bar` of a (possibly nested) `use foo::{Baz as bar};` tree to the `use`
+    /// item it belongs to.
+    pub(super) fn use_rename_to_def(&mut self, src: InFile<&ast::Rename>) -> Option<UseId> {
+        let use_tree = ast::UseTree::cast(src.value.syntax().parent()?)?;
+        let use_ = use_tree.syntax().ancestors().find_map(ast::Use::cast)?;
+        let id = self.use_to_def(src.with_value(&use_))?;
+        // Make sure the tree was actually lowered as part of that `use`, e.g. it isn't lost in
+        // an unparsable group.
+        let ptr = AstPtr::new(&use_tree);
+        let trees = id.child_source(self.db.upcast()).value;
+        trees.values().any(|tree| AstPtr::new(tree) == ptr).then_some(id)
+    }
+    /// Resolves the path of a glob import like `use foo::bar::*;`, `use self::*;` or
+    /// `use crate::{foo::*};` to the module it imports from. Globs importing enum variants
+    /// resolve to nothing.
+    pub(super) fn use_glob_to_def(&mut self, src: InFile<&ast::UseTree>) -> Option<ModuleId> {
+        src.value.star_token()?;
+        let (id, path, kind) = self.use_tree_import(src)?;
+        if kind != ImportKind::Glob {
+            return None;
+        }
+        let resolver = id.resolver(self.db.upcast());
+        match resolver.resolve_module_path_in_items(self.db.upcast(), &path).take_types()? {
+            ModuleDefId::ModuleId(module) => Some(module),
+            _ => None,
+        }
+    }
+    /// Resolves a single leaf of a (possibly grouped) import, like the `b` or the `c::d` in
+    /// `use a::{b, c::d};`, to the item it imports. Names imported in several namespaces resolve
+    /// to the type, then to the value. Globs and the prefixes of groups aren't leaves.
+    pub(super) fn use_tree_leaf_to_def(&mut self, src: InFile<&ast::UseTree>) -> Option<AnyDef> {
+        let (id, path, kind) = self.use_tree_import(src)?;
+        let resolver = id.resolver(self.db.upcast());
+        let per_ns = resolver.resolve_module_path_in_items(self.db.upcast(), &path);
+        match kind {
+            ImportKind::Glob => None,
+            ImportKind::TypeOnly => AnyDef::from_module_def(per_ns.take_types()?.into()),
+            ImportKind::Plain => match per_ns.take_types().or(per_ns.take_values()) {
+                Some(def) => AnyDef::from_module_def(def.into()),
+                None => per_ns.take_macros().map(|it| Macro::from(it).into()),
+            },
+        }
+    }
+    /// Returns the `use` item that `src` is part of, along with the full path `src` imports and
+    /// how it does so. Only leaves of the tree import anything.
+    fn use_tree_import(
+        &mut self,
+        src: InFile<&ast::UseTree>,
+    ) -> Option<(UseId, ModPath, ImportKind)> {
+        let use_ = src.value.syntax().ancestors().find_map(ast::Use::cast)?;
+        let id = self.use_to_def(src.with_value(&use_))?;
+        let ptr = AstPtr::new(src.value);
+        let trees = id.child_source(self.db.upcast()).value;
+        let (index, _) = trees.iter().find(|(_, tree)| AstPtr::new(*tree) == ptr)?;
+
+        let loc = id.lookup(self.db.upcast());
+        let item_tree = loc.id.item_tree(self.db.upcast());
+        let mut import = None;
+        item_tree[loc.id.value].use_tree.expand(|idx, path, kind, _| {
+            if idx == index {
+                import = Some((path, kind));
+            }
+        });
+        let (path, kind) = import?;
+        Some((id, path, kind))
+    }
+    pub(super) fn extern_block_to_def(
+        &mut self,
+        src: InFile<&ast::ExternBlock>,
+    ) -> Option<ExternBlockId> {
+        self.to_def(src, keys::EXTERN_BLOCK)
+    }
     pub(super) fn adt_to_def(
         &mut self,
         InFile { file_id, value }: InFile<&ast::Adt>,
@@ -258,17 +639,55 @@ impl SourceToDefCtx<'_, '_> {
         &mut self,
         src: InFile<&ast::IdentPat>,
     ) -> Option<(DefWithBodyId, BindingId)> {
+        self.ident_pat_to_def(src)?.left()
+    }
+    /// Resolves an ident pattern to the binding it introduces or, if it instead names a constant
+    /// or unit variant (e.g. `None`), to the path pattern it was lowered to.
+    pub(super) fn ident_pat_to_def(
+        &mut self,
+        src: InFile<&ast::IdentPat>,
+    ) -> Option<Either<(DefWithBodyId, BindingId), (DefWithBodyId, PatId)>> {
         let container = self.find_pat_or_label_container(src.syntax_ref())?;
         let (body, source_map) = self.db.body_with_source_map(container);
         let src = src.cloned().map(ast::Pat::from);
         let pat_id = source_map.node_pat(src.as_ref())?;
-        // the pattern could resolve to a constant, verify that that is not the case
-        if let crate::Pat::Bind { id, .. } = body[pat_id] {
-            Some((container, id))
-        } else {
-            None
+        match body[pat_id] {
+            crate::Pat::Bind { id, .. } => Some(Either::Left((container, id))),
+            crate::Pat::Path(_) => Some(Either::Right((container, pat_id))),
+            _ => None,
         }
     }
+    /// Resolves the implicit capture at `offset` in the template of a `format_args!` expression,
+    /// e.g. the `x` in `"{x}"`, to the binding it refers to. `offset` is relative to the start of
+    /// the template string's contents.
+    pub(super) fn format_args_capture_to_def(
+        &mut self,
+        src: InFile<&ast::FormatArgsExpr>,
+        offset: TextSize,
+    ) -> Option<(TextRange, (DefWithBodyId, BindingId))> {
+        let container = self.find_pat_or_label_container(src.syntax_ref())?;
+        let (_, source_map) = self.db.body_with_source_map(container);
+        let (range, name) = source_map
+            .implicit_format_args(src)?
+            .iter()
+            .find(|(range, _)| range.contains_inclusive(offset))?;
+        let expr = source_map.node_expr(src.cloned().map(ast::Expr::from).as_ref())?;
+        let scopes = self.db.expr_scopes(container);
+        let entry = scopes.resolve_name_in_scope(scopes.scope_for(expr)?, name)?;
+        Some((*range, (container, entry.binding())))
+    }
+    /// Resolves a method call to the method inferred for its receiver. Calls of trait methods
+    /// resolve to the method's declaration in the trait, not to an implementation of it.
+    pub(super) fn method_call_to_def(
+        &mut self,
+        src: InFile<&ast::MethodCallExpr>,
+    ) -> Option<FunctionId> {
+        let container = self.find_pat_or_label_container(src.syntax_ref())?;
+        let (_, source_map) = self.db.body_with_source_map(container);
+        let expr = source_map.node_expr(src.cloned().map(ast::Expr::from).as_ref())?;
+        let (func, _) = self.db.infer(container).method_resolution(expr)?;
+        Some(func)
+    }
     pub(super) fn self_param_to_def(
         &mut self,
         src: InFile<&ast::SelfParam>,
@@ -292,6 +711,15 @@ impl SourceToDefCtx<'_, '_> {
         map[keys::ATTR_MACRO_CALL].get(&AstPtr::new(src.value)).copied()
     }
 
+    /// Resolves `src` to the attribute macro call it is the input of, falling back to the
+    /// definition it declares.
+    pub(super) fn resolve_or_expand(&mut self, src: InFile<&ast::Item>) -> Option<ItemResolution> {
+        if let Some(call) = self.item_to_macro_call(src) {
+            return Some(ItemResolution::MacroCall(call.as_macro_file()));
+        }
+        self.node_to_def(src.syntax_ref()).map(ItemResolution::Def)
+    }
+
     pub(super) fn macro_call_to_macro_call(
         &mut self,
         src: InFile<&ast::MacroCall>,
@@ -300,6 +728,67 @@ impl SourceToDefCtx<'_, '_> {
         map[keys::MACRO_CALL].get(&AstPtr::new(src.value)).copied()
     }
 
+    /// Resolves a segment of the path of the macro call `call`: the last segment to the called
+    /// macro, and a segment of its qualifier to the module it names. Qualifiers are resolved in
+    /// the scope of the item containing the call, so modules local to a block aren't seen.
+    pub(super) fn macro_call_path_segment_to_def(
+        &mut self,
+        call: InFile<&ast::MacroCall>,
+        seg: InFile<&ast::PathSegment>,
+    ) -> Option<AnyDef> {
+        let path = seg.value.parent_path();
+        if call.value.path()? == path {
+            let call_id = self.macro_call_to_macro_call(call)?;
+            return super::macro_call_to_macro_id(self, call_id).map(|id| Macro::from(id).into());
+        }
+        if !path.syntax().ancestors().any(|it| it == *call.value.syntax()) {
+            return None;
+        }
+
+        let db = self.db.upcast();
+        let span_map = self.db.span_map(call.file_id);
+        let mod_path =
+            ModPath::from_src(db, path, &mut |range| span_map.span_for_range(range).ctx)?;
+        let container = self.find_container(call.syntax_ref())?;
+        let resolver = container.resolver(self.db);
+        let def =
+            resolver.resolve_module_path_in_items(self.db.upcast(), &mod_path).take_types()?;
+        AnyDef::from_module_def(def.into())
+    }
+
+    /// Resolves the path of the attribute `src`: a compiler or registered attribute like
+    /// `#[inline]` to the builtin attribute, an attribute macro to its definition and a tool
+    /// attribute like `#[rustfmt::skip]` to the tool. Outer attributes are resolved in the scope
+    /// around the item they are attached to, inner ones in the item itself.
+    pub(super) fn attr_path_to_def(&mut self, src: InFile<&ast::Attr>) -> Option<AnyDef> {
+        let path = src.value.path()?;
+        let owner = src.value.syntax().parent()?;
+        let scope = match src.value.kind() {
+            ast::AttrKind::Inner => owner,
+            ast::AttrKind::Outer => owner.parent().unwrap_or(owner),
+        };
+        let container = self.find_container(src.with_value(&scope))?;
+        let resolver = container.resolver(self.db);
+        let krate = Crate::from(resolver.krate());
+
+        if let Some(name_ref) = path.as_single_name_ref() {
+            if let Some(it) = BuiltinAttr::by_name(self.db, krate, &name_ref.text()) {
+                return Some(AnyDef::BuiltinAttr(it));
+            }
+        }
+        let span_map = self.db.span_map(src.file_id);
+        let mod_path = ModPath::from_src(self.db.upcast(), path.clone(), &mut |range| {
+            span_map.span_for_range(range).ctx
+        })?;
+        if let Some((it, _)) =
+            resolver.resolve_path_as_macro(self.db.upcast(), &mod_path, Some(MacroSubNs::Attr))
+        {
+            return Some(Macro::from(it).into());
+        }
+        let tool = path.first_segment()?.name_ref()?;
+        ToolModule::by_name(self.db, krate, &tool.text()).map(AnyDef::ToolModule)
+    }
+
     /// (AttrId, derive attribute call id, derive call ids)
     pub(super) fn attr_to_derive_macro_call(
         &mut self,
@@ -316,12 +805,390 @@ impl SourceToDefCtx<'_, '_> {
         self.dyn_map(adt).as_ref().map_or(false, |map| !map[keys::DERIVE_MACRO_CALL].is_empty())
     }
 
+    /// Builds the child maps holding the derive macro calls of all adts in `file` up front, so
+    /// that resolving their derives one by one afterwards only hits the cache.
+    pub(super) fn prewarm_derives(&mut self, file: HirFileId) {
+        let _p = tracing::info_span!("SourceToDefCtx::prewarm_derives").entered();
+        let root = self.db.parse_or_expand(file);
+        // Only attributed adts can have derives.
+        for adt in root.descendants().filter_map(ast::Adt::cast) {
+            if adt.attrs().next().is_none() {
+                continue;
+            }
+            if let Some(container) = self.find_container(InFile::new(file, adt.syntax())) {
+                self.cache_for(container, file);
+            }
+        }
+    }
+
+    /// Builds the child maps of the modules defined in `file`, and of the impls, traits and adts
+    /// declared in them, in a single walk over their item scopes. Later lookups in the file then
+    /// only hit the cache instead of each searching for its container first.
+    pub(super) fn prewarm_file(&mut self, file: FileId) {
+        let _p = tracing::info_span!("SourceToDefCtx::prewarm_file").entered();
+        let file_id = HirFileId::from(file);
+        let mut modules = self.file_to_def_cloned(file).into_vec();
+        while let Some(module) = modules.pop() {
+            self.cache_for(module.into(), file_id);
+            let def_map = module.def_map(self.db.upcast());
+            let data = &def_map[module.local_id];
+            for &child in data.children.values() {
+                // Modules with files of their own are warmed with their own file.
+                if let ModuleOrigin::Inline { definition_tree_id, .. } = def_map[child].origin {
+                    if definition_tree_id.file_id() == file_id {
+                        modules.push(def_map.module_id(child));
+                    }
+                }
+            }
+            for impl_ in data.scope.impls() {
+                self.cache_for(impl_.into(), file_id);
+            }
+            for def in data.scope.declarations() {
+                let container: ChildContainer = match def {
+                    ModuleDefId::TraitId(it) => it.into(),
+                    ModuleDefId::AdtId(AdtId::EnumId(it)) => it.into(),
+                    ModuleDefId::AdtId(AdtId::StructId(it)) => VariantId::from(it).into(),
+                    ModuleDefId::AdtId(AdtId::UnionId(it)) => VariantId::from(it).into(),
+                    _ => continue,
+                };
+                self.cache_for(container, file_id);
+            }
+        }
+    }
+
+    /// Resolves `src` like [`Self::node_to_def`], and again for each other crate its file is part
+    /// of. The other resolutions use a scratch cache, which maps the file to that crate's module
+    /// only, so that none of the containers found for the primary resolution get reused.
+    pub(super) fn node_to_def_detailed(
+        &mut self,
+        src: InFile<&SyntaxNode>,
+    ) -> Option<ResolveResult> {
+        let primary = self.node_to_def(src)?;
+        let file = src.file_id.original_file(self.db.upcast());
+        let modules = self.file_to_def(file).clone();
+        let mut alternatives = SmallVec::new();
+        for &module in modules.iter().skip(1) {
+            let mut cache = SourceToDefCache::default();
+            cache.file_to_def_cache.insert(file, smallvec![module]);
+            let mut ctx = SourceToDefCtx {
+                db: self.db,
+                cache: &mut cache,
+                d2s_cache: self.d2s_cache,
+                climb_out_of_macros: self.climb_out_of_macros,
+            };
+            match ctx.node_to_def(src) {
+                Some(def) if def != primary && !alternatives.contains(&def) => {
+                    alternatives.push(def)
+                }
+                _ => (),
+            }
+        }
+        Some(ResolveResult { primary, alternatives })
+    }
+
+    /// Like [`Self::node_to_def`], but resolves against scratch caches that are dropped
+    /// afterwards, for speculative lookups whose data is unlikely to be reused. Nothing cached
+    /// by `self` is read or changed.
+    pub(super) fn resolve_no_cache(&self, src: InFile<&SyntaxNode>) -> Option<AnyDef> {
+        let mut cache = SourceToDefCache::default();
+        let d2s_cache = DefToSrcCache::default();
+        let mut ctx = SourceToDefCtx {
+            db: self.db,
+            cache: &mut cache,
+            d2s_cache: &d2s_cache,
+            climb_out_of_macros: self.climb_out_of_macros,
+        };
+        ctx.node_to_def(src)
+    }
+
+    /// Resolves the `{ ... }` body of a trait or impl to the trait or impl owning it.
+    pub(super) fn assoc_item_list_to_def(
+        &mut self,
+        src: InFile<&ast::AssocItemList>,
+    ) -> Option<AnyDef> {
+        let parent = src.value.syntax().parent()?;
+        match_ast! {
+            match parent {
+                ast::Trait(it) => {
+                    self.trait_to_def(src.with_value(&it)).map(|it| Trait::from(it).into())
+                },
+                ast::Impl(it) => {
+                    self.impl_to_def(src.with_value(&it)).map(|it| Impl::from(it).into())
+                },
+                _ => None,
+            }
+        }
+    }
+
+    pub(super) fn node_to_def(&mut self, src: InFile<&SyntaxNode>) -> Option<AnyDef> {
+        let InFile { file_id, value } = src;
+        match_ast! {
+            match value {
+                ast::SourceFile(it) => self
+                    .source_file_to_def(InFile::new(file_id, &it))
+                    .map(|it| Module::from(it).into()),
+                ast::Module(it) => self
+                    .module_to_def(InFile::new(file_id, &it))
+                    .map(|it| Module::from(it).into()),
+                ast::Struct(it) => self
+                    .struct_to_def(InFile::new(file_id, &it))
+                    .map(|it| Struct::from(it).into()),
+                ast::Enum(it) => self
+                    .enum_to_def(InFile::new(file_id, &it))
+                    .map(|it| Enum::from(it).into()),
+                ast::Union(it) => self
+                    .union_to_def(InFile::new(file_id, &it))
+                    .map(|it| Union::from(it).into()),
+                ast::Trait(it) => self
+                    .trait_to_def(InFile::new(file_id, &it))
+                    .map(|it| Trait::from(it).into()),
+                ast::TraitAlias(it) => self
+                    .trait_alias_to_def(InFile::new(file_id, &it))
+                    .map(|it| TraitAlias::from(it).into()),
+                ast::Impl(it) => self
+                    .impl_to_def(InFile::new(file_id, &it))
+                    .map(|it| Impl::from(it).into()),
+                ast::TypeAlias(it) => self
+                    .type_alias_to_def(InFile::new(file_id, &it))
+                    .map(|it| TypeAlias::from(it).into()),
+                ast::Const(it) => self
+                    .const_to_def(InFile::new(file_id, &it))
+                    .map(|it| Const::from(it).into()),
+                ast::Static(it) => self
+                    .static_to_def(InFile::new(file_id, &it))
+                    .map(|it| Static::from(it).into()),
+                ast::Fn(it) => {
+                    let src = InFile::new(file_id, &it);
+                    match self.fn_to_def(src) {
+                        Some(it) => Some(Function::from(it).into()),
+                        None => self.proc_macro_to_def(src).map(|it| Macro::from(it).into()),
+                    }
+                },
+                ast::RecordField(it) => self
+                    .record_field_to_def(InFile::new(file_id, &it))
+                    .map(|it| Field::from(it).into()),
+                ast::TupleField(it) => self
+                    .tuple_field_to_def(InFile::new(file_id, &it))
+                    .map(|it| Field::from(it).into()),
+                ast::Variant(it) => self
+                    .enum_variant_to_def(InFile::new(file_id, &it))
+                    .map(|it| Variant::from(it).into()),
+                ast::TypeParam(it) => self
+                    .type_param_to_def(InFile::new(file_id, &it))
+                    .map(|it| TypeParam::from(it).into()),
+                ast::LifetimeParam(it) => self
+                    .lifetime_param_to_def(InFile::new(file_id, &it))
+                    .map(|it| LifetimeParam::from(it).into()),
+                ast::ConstParam(it) => self
+                    .const_param_to_def(InFile::new(file_id, &it))
+                    .map(|it| ConstParam::from(it).into()),
+                ast::Macro(it) => self
+                    .macro_to_def(InFile::new(file_id, &it))
+                    .map(|it| Macro::from(it).into()),
+                ast::IdentPat(it) => self
+                    .bind_pat_to_def(InFile::new(file_id, &it))
+                    .map(|it| Local::from(it).into()),
+                ast::SelfParam(it) => self
+                    .self_param_to_def(InFile::new(file_id, &it))
+                    .map(|it| Local::from(it).into()),
+                ast::Label(it) => self
+                    .label_to_def(InFile::new(file_id, &it))
+                    .map(|it| Label::from(it).into()),
+                ast::ExternCrate(it) => self
+                    .extern_crate_to_def(InFile::new(file_id, &it))
+                    .map(|it| ExternCrateDecl::from(it).into()),
+                _ => None,
+            }
+        }
+    }
+
+    /// Like [`Self::node_to_def`], but reports nodes that are disabled by a `#[cfg]` on
+    /// themselves or one of their ancestors as [`ResolveOutcome::CfgDisabled`]. Such nodes are
+    /// not part of the def map, so they never resolve.
+    pub(super) fn node_to_def_outcome(&mut self, src: InFile<&SyntaxNode>) -> ResolveOutcome {
+        if let Some(def) = self.node_to_def(src) {
+            return ResolveOutcome::Def(def);
+        }
+        if self.is_cfg_disabled(src) {
+            ResolveOutcome::CfgDisabled
+        } else {
+            ResolveOutcome::Unresolved
+        }
+    }
+
+    /// Returns whether `src` or one of its ancestors has a `#[cfg]` that is disabled in the crate
+    /// of its file. Only the ancestors within the file are checked.
+    fn is_cfg_disabled(&mut self, src: InFile<&SyntaxNode>) -> bool {
+        let Some(&module) = self.file_to_def(src.file_id.original_file(self.db.upcast())).first()
+        else {
+            return false;
+        };
+        let krate = module.krate();
+        let crate_graph = self.db.crate_graph();
+        let cfg_options = &crate_graph[krate].cfg_options;
+        let span_map = self.db.span_map(src.file_id);
+        src.value.ancestors().filter_map(ast::AnyHasAttrs::cast).any(|owner| {
+            let attrs = RawAttrs::new(self.db.upcast(), &owner, span_map.as_ref())
+                .filter(self.db.upcast(), krate);
+            attrs
+                .iter()
+                .filter_map(|attr| attr.cfg())
+                .any(|cfg| cfg_options.check(&cfg) == Some(false))
+        })
+    }
+
+    /// Like [`Self::node_to_def`], but also returns the range to focus when navigating to the
+    /// definition: the range of its name, or the whole node for unnamed definitions like impls.
+    pub(super) fn node_to_def_with_range(
+        &mut self,
+        src: InFile<&SyntaxNode>,
+    ) -> Option<(AnyDef, InFile<TextRange>)> {
+        let def = self.node_to_def(src)?;
+        let range = ast::AnyHasName::cast(src.value.clone())
+            .and_then(|it| it.name())
+            .map_or_else(|| src.value.text_range(), |name| name.syntax().text_range());
+        Some((def, src.with_value(range)))
+    }
+
+    /// Resolves the name of a declaration to the definition it declares.
+    pub(super) fn name_to_def(&mut self, src: InFile<&ast::Name>) -> Option<AnyDef> {
+        let parent = src.value.syntax().parent()?;
+        self.node_to_def(src.with_value(&parent))
+    }
+
+    /// Resolves `token` to the narrowest definition enclosing it, like the struct for the name
+    /// token in `struct Foo;` or the local for the `x` in `let x = 0;`. Tokens in macro
+    /// expansions climb out of the expansion once it has no enclosing definition left.
+    pub(super) fn token_to_def(&mut self, token: InFile<&SyntaxToken>) -> Option<AnyDef> {
+        let parent = token.value.parent()?;
+        let src = token.with_value(&parent);
+        if let Some(def) = self.node_to_def(src) {
+            return Some(def);
+        }
+        self.ancestors_with_macros(src, |this, node| this.node_to_def(node.as_ref()))
+    }
+
+    /// Reports whether `token`, which must be part of a macro expansion, was passed to the macro
+    /// by its caller or stems from the macro definition itself.
+    pub(super) fn hygiene_origin(&mut self, token: InFile<&SyntaxToken>) -> Option<HygieneOrigin> {
+        let macro_file = token.file_id.macro_file()?;
+        let db = self.db.upcast();
+        let info = self.expand_and_map(macro_file.macro_call_id);
+        let mapped = info.map_range_up_once(db, token.value.text_range());
+        let arg = info.arg();
+        let from_call = mapped.file_id == arg.file_id
+            && arg.value.is_some_and(|arg| {
+                mapped.value.iter().any(|&range| arg.text_range().contains_range(range))
+            });
+        Some(if from_call { HygieneOrigin::CallSite } else { HygieneOrigin::DefSite })
+    }
+
+    /// Returns the [`ExpansionInfo`] of the macro call `call`, computing and caching it first if
+    /// necessary.
+    pub(super) fn expand_and_map(&mut self, call: MacroCallId) -> &ExpansionInfo {
+        let macro_file = self.macro_call_to_expanded_file(call);
+        &self.cache.expansion_info_cache[&macro_file]
+    }
+
+    /// Returns the file the macro call `call` expands to, caching its [`ExpansionInfo`] like
+    /// [`Self::expand_and_map`].
+    pub(super) fn macro_call_to_expanded_file(&mut self, call: MacroCallId) -> MacroFileId {
+        let macro_file = call.as_macro_file();
+        self.expansion_info(macro_file);
+        macro_file
+    }
+
+    /// Returns the [`ExpansionInfo`] of `macro_file`, computing and caching it first if
+    /// necessary. A freshly computed expansion is handed to the def-to-src cache as well, so that
+    /// sources looked up in the macro file afterwards are part of the same tree.
+    pub(super) fn expansion_info(&mut self, macro_file: MacroFileId) -> &ExpansionInfo {
+        let cache = &mut *self.cache;
+        match cache.expansion_info_cache.entry(macro_file) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                cache.stats.expansions += 1;
+                let info = macro_file.expansion_info(self.db.upcast());
+                self.d2s_cache.insert_parsed(macro_file.into(), info.expanded().value);
+                entry.insert(info)
+            }
+        }
+    }
+
+    /// Resolves the node `ptr` points to in the expansion of `call` to the definition it
+    /// declares.
+    pub(super) fn expansion_node_to_def(
+        &mut self,
+        call: MacroCallId,
+        ptr: SyntaxNodePtr,
+    ) -> Option<AnyDef> {
+        let expanded = self.expand_and_map(call).expanded();
+        let node = ptr.try_to_node(&expanded.value)?;
+        self.node_to_def(InFile::new(expanded.file_id.into(), &node))
+    }
+
+    /// Resolves the intra-doc link `link`, like `foo::Bar` or `fn@foo`, in the scope of the item
+    /// that the doc attribute or doc comment owned by `src` documents.
+    pub(super) fn doc_link_to_def(
+        &mut self,
+        src: InFile<&SyntaxNode>,
+        link: &str,
+    ) -> Option<AnyDef> {
+        let owner = src.value.ancestors().find_map(|node| {
+            match self.node_to_def(InFile::new(src.file_id, &node))? {
+                AnyDef::Local(_) | AnyDef::Label(_) => None,
+                def => Some(def),
+            }
+        })?;
+        resolve_doc_link_on_any_def(self.db, owner, link)
+    }
+
+    /// Resolves a batch of nodes, see [`Self::node_to_def`].
+    ///
+    /// The nodes are resolved in source order per file, so nodes sharing a container are
+    /// resolved back to back and reuse that container's cached child map.
+    pub(super) fn nodes_to_defs<'a>(
+        &mut self,
+        nodes: impl IntoIterator<Item = InFile<&'a SyntaxNode>>,
+    ) -> Vec<Option<AnyDef>> {
+        let nodes: Vec<_> = nodes.into_iter().collect();
+        let mut res = vec![None; nodes.len()];
+        // Children of containers are looked up in the child map of their container, which is
+        // fetched once per container. Everything else is resolved one by one.
+        let mut by_container: FxHashMap<(ChildContainer, HirFileId), Vec<usize>> =
+            FxHashMap::default();
+        for (idx, &node) in nodes.iter().enumerate() {
+            if !is_container_child(node.value.kind()) {
+                res[idx] = self.node_to_def(node);
+                continue;
+            }
+            if let Some(container) = self.find_container(node) {
+                by_container.entry((container, node.file_id)).or_default().push(idx);
+            }
+        }
+        for ((container, file_id), idxs) in by_container {
+            let dyn_map = self.cache_for(container, file_id);
+            for idx in idxs {
+                res[idx] = container_child_to_def(dyn_map, nodes[idx].value);
+            }
+        }
+        res
+    }
+
     fn to_def<Ast: AstNode + 'static, ID: Copy + 'static>(
         &mut self,
         src: InFile<&Ast>,
         key: Key<Ast, ID>,
     ) -> Option<ID> {
-        self.dyn_map(src)?[key].get(&AstPtr::new(src.value)).copied()
+        let miss =
+            (TypeId::of::<Key<Ast, ID>>(), SyntaxNodePtr::new(src.value.syntax()), src.file_id);
+        if self.cache.to_def_misses.contains(&miss) {
+            return None;
+        }
+        let res = self.dyn_map(src).and_then(|map| map[key].get(&AstPtr::new(src.value)).copied());
+        if res.is_none() {
+            self.cache.to_def_misses.insert(miss);
+        }
+        res
     }
 
     fn dyn_map<Ast: AstNode + 'static>(&mut self, src: InFile<&Ast>) -> Option<&DynMap> {
@@ -329,12 +1196,38 @@ impl SourceToDefCtx<'_, '_> {
         Some(self.cache_for(container, src.file_id))
     }
 
+    /// Renders the [`DynMap`] of the container `src` is resolved in, see [`DynMap::debug_dump`].
+    pub(super) fn dump_container(&mut self, src: InFile<&SyntaxNode>) -> Option<String> {
+        let container = self.find_container(src)?;
+        Some(self.cache_for(container, src.file_id).debug_dump())
+    }
+
     fn cache_for(&mut self, container: ChildContainer, file_id: HirFileId) -> &DynMap {
         let db = self.db;
-        self.cache
-            .dynmap_cache
-            .entry((container, file_id))
-            .or_insert_with(|| container.child_by_source(db, file_id))
+        let cache = &mut *self.cache;
+        let key = (container, file_id);
+        if cache.dynmap_cache.contains_key(&key) {
+            cache.stats.hits += 1;
+            if let Some(lru) = &mut cache.dynmap_lru {
+                lru.touch(key);
+            }
+            return &cache.dynmap_cache[&key];
+        }
+        // Don't cache a map per file for containers whose children all live in one file, the
+        // map for any other file is empty.
+        if container.fixed_file(db).map_or(false, |it| it != file_id) {
+            return &cache.empty_dynmap;
+        }
+        cache.stats.misses += 1;
+        if cache.evicted_dynmaps.remove(&key) {
+            cache.dynmap_rebuilds += 1;
+        }
+        let dyn_map = container.child_by_source(db, file_id);
+        if let Some(capacity) = cache.dynmap_lru.as_ref().map(|lru| lru.capacity) {
+            cache.shrink_to(capacity - 1);
+            cache.dynmap_lru.as_mut().unwrap().touch(key);
+        }
+        cache.dynmap_cache.entry(key).or_insert(dyn_map)
     }
 
     pub(super) fn type_param_to_def(
@@ -358,6 +1251,23 @@ impl SourceToDefCtx<'_, '_> {
         dyn_map[keys::LIFETIME_PARAM].get(&AstPtr::new(src.value)).copied()
     }
 
+    /// Resolves a lifetime usage, like the `'a` in `&'a T`, to the lifetime parameter it refers
+    /// to. `'static` and elided lifetimes don't refer to a parameter and resolve to `None`.
+    pub(super) fn lifetime_to_def(
+        &mut self,
+        src: InFile<&ast::Lifetime>,
+    ) -> Option<LifetimeParamId> {
+        if let Some(param) = src.value.syntax().parent().and_then(ast::LifetimeParam::cast) {
+            return self.lifetime_param_to_def(src.with_value(&param));
+        }
+        let container = self.find_generic_param_container(src.syntax_ref())?;
+        let lifetime = LifetimeRef { name: Name::new_lifetime(src.value) };
+        match container.resolver(self.db.upcast()).resolve_lifetime(&lifetime)? {
+            LifetimeNs::LifetimeParam(it) => Some(it),
+            LifetimeNs::Static => None,
+        }
+    }
+
     pub(super) fn const_param_to_def(
         &mut self,
         src: InFile<&ast::ConstParam>,
@@ -370,6 +1280,65 @@ impl SourceToDefCtx<'_, '_> {
             .map(ConstParamId::from_unchecked)
     }
 
+    /// Resolves an expression in the default value of a const parameter, like the `4` in
+    /// `const N: usize = 4`, to the const parameter owning it.
+    pub(super) fn const_param_default_to_def(
+        &mut self,
+        src: InFile<&ast::Expr>,
+    ) -> Option<ConstParamId> {
+        let param = src.value.syntax().ancestors().find_map(ast::ConstParam::cast)?;
+        let default = param.default_val()?;
+        if !default.syntax().text_range().contains_range(src.value.syntax().text_range()) {
+            return None;
+        }
+        self.const_param_to_def(src.with_value(&param))
+    }
+
+    /// Resolves a const operand of a where clause, like the `N` in `where [(); N]: Sized`, to the
+    /// item owning the where clause. Unlike const items, these operands have no body of their own
+    /// and are lowered as part of the item's signature.
+    pub(super) fn where_clause_const_to_def(
+        &mut self,
+        src: InFile<&ast::Expr>,
+    ) -> Option<GenericDefId> {
+        let const_arg = ast::ConstArg::cast(src.value.syntax().parent()?)?;
+        let where_clause = const_arg.syntax().ancestors().find_map(ast::WhereClause::cast)?;
+        self.find_generic_param_container(src.with_value(where_clause.syntax()))
+    }
+
+    /// Resolves an argument-position `impl Trait` to the synthetic type parameter it introduces
+    /// on the enclosing function.
+    pub(super) fn impl_trait_type_to_def(
+        &mut self,
+        src: InFile<&ast::ImplTraitType>,
+    ) -> Option<TypeParamId> {
+        let param_list = src.value.syntax().ancestors().find_map(ast::ParamList::cast)?;
+        let fn_ = ast::Fn::cast(param_list.syntax().parent()?)?;
+        let func = self.fn_to_def(src.with_value(&fn_))?;
+
+        // The synthetic params are allocated in the order the `impl Trait`s appear in the
+        // parameter types, self parameter first.
+        let idx = param_list
+            .self_param()
+            .and_then(|it| it.ty())
+            .into_iter()
+            .chain(param_list.params().filter_map(|it| it.ty()))
+            .flat_map(|ty| ty.syntax().descendants())
+            .filter_map(ast::ImplTraitType::cast)
+            .position(|it| it == *src.value)?;
+
+        let parent = GenericDefId::FunctionId(func);
+        let generic_params = self.db.generic_params(parent);
+        let (local_id, _) = generic_params
+            .iter_type_or_consts()
+            .filter(|(_, data)| {
+                data.type_param()
+                    .map_or(false, |it| it.provenance == TypeParamProvenance::ArgumentImplTrait)
+            })
+            .nth(idx)?;
+        Some(TypeParamId::from_unchecked(TypeOrConstParamId { parent, local_id }))
+    }
+
     pub(super) fn generic_param_to_def(
         &mut self,
         InFile { file_id, value }: InFile<&ast::GenericParam>,
@@ -404,10 +1373,33 @@ impl SourceToDefCtx<'_, '_> {
         })
     }
 
+    /// Finds the container `src` is declared in.
+    ///
+    /// Results are cached per node: resolving the containers of ancestor items is part of every
+    /// container search (and of [`Self::find_generic_param_container`]), so nodes that share
+    /// ancestors, like the generic params of a function, only climb to the function once.
     pub(super) fn find_container(&mut self, src: InFile<&SyntaxNode>) -> Option<ChildContainer> {
+        let key = (SyntaxNodePtr::new(src.value), src.file_id);
+        if let Some(&container) = self.cache.container_cache.get(&key) {
+            return container;
+        }
+        self.cache.stats.container_searches += 1;
+        let container = self.find_container_uncached(src);
+        self.cache.container_cache.insert(key, container);
+        container
+    }
+
+    fn find_container_uncached(&mut self, src: InFile<&SyntaxNode>) -> Option<ChildContainer> {
         let _p = tracing::info_span!("find_container").entered();
-        let def =
-            self.ancestors_with_macros(src, |this, container| this.container_to_def(container));
+        let def = self.ancestors_with_macros(src, |this, container| {
+            // Only items and enum variants can be containers, so don't bother with the
+            // expressions, types etc. in between.
+            let kind = container.value.kind();
+            if !ast::Item::can_cast(kind) && !ast::Variant::can_cast(kind) {
+                return None;
+            }
+            this.container_to_def(container)
+        });
         if let Some(def) = def {
             return Some(def);
         }
@@ -417,6 +1409,15 @@ impl SourceToDefCtx<'_, '_> {
     }
 
     /// Skips the attributed item that caused the macro invocation we are climbing up
+    ///
+    /// When leaving a macro file, we continue at the parent of the macro call's argument. For
+    /// attribute and derive macros, the argument is the attributed item itself. The expanded
+    /// items take its place in (or are added next to it in) its parent, so the attributed item
+    /// is not visited as one of their ancestors. E.g. the `impl` a derive generates is found in
+    /// the module of the derived adt, not in the adt itself.
+    ///
+    /// Unless [`Self::climb_out_of_macros`] is set, the macro call itself is visited instead
+    /// when leaving a macro file, and the walk ends there.
     fn ancestors_with_macros<T>(
         &mut self,
         node: InFile<&SyntaxNode>,
@@ -427,14 +1428,10 @@ impl SourceToDefCtx<'_, '_> {
             Some(parent) => Some(node.with_value(parent)),
             None => {
                 let macro_file = node.file_id.macro_file()?;
-
-                let expansion_info = this
-                    .cache
-                    .expansion_info_cache
-                    .entry(macro_file)
-                    .or_insert_with(|| macro_file.expansion_info(this.db.upcast()));
-
-                expansion_info.arg().map(|node| node?.parent()).transpose()
+                if !this.climb_out_of_macros {
+                    return Some(macro_file.call_node(this.db.upcast()));
+                }
+                this.expansion_info(macro_file).arg().map(|node| node?.parent()).transpose()
             }
         };
         let mut node = node.cloned();
@@ -442,11 +1439,16 @@ impl SourceToDefCtx<'_, '_> {
             if let Some(res) = cb(self, parent.clone()) {
                 return Some(res);
             }
+            if !self.climb_out_of_macros && parent.file_id != node.file_id {
+                break;
+            }
             node = parent;
         }
         None
     }
 
+    /// Finds the item declaring the generic parameter `src`. Every item kind that can have a
+    /// generic parameter list must be handled here, otherwise its parameters don't resolve.
     fn find_generic_param_container(&mut self, src: InFile<&SyntaxNode>) -> Option<GenericDefId> {
         self.ancestors_with_macros(src, |this, InFile { file_id, value }| {
             let item = ast::Item::cast(value)?;
@@ -455,6 +1457,7 @@ impl SourceToDefCtx<'_, '_> {
                 ast::Item::Struct(it) => {
                     this.struct_to_def(InFile::new(file_id, it)).map(Into::into)
                 }
+                ast::Item::Union(it) => this.union_to_def(InFile::new(file_id, it)).map(Into::into),
                 ast::Item::Enum(it) => this.enum_to_def(InFile::new(file_id, it)).map(Into::into),
                 ast::Item::Trait(it) => this.trait_to_def(InFile::new(file_id, it)).map(Into::into),
                 ast::Item::TraitAlias(it) => {
@@ -500,6 +1503,9 @@ impl SourceToDefCtx<'_, '_> {
                     self.trait_alias_to_def(container.with_value(it))?.into()
                 }
                 ast::Item::Impl(it) => self.impl_to_def(container.with_value(it))?.into(),
+                ast::Item::ExternBlock(it) => {
+                    self.extern_block_to_def(container.with_value(it))?.into()
+                }
                 ast::Item::Enum(it) => self.enum_to_def(container.with_value(it))?.into(),
                 ast::Item::TypeAlias(it) => {
                     self.type_alias_to_def(container.with_value(it))?.into()
@@ -542,6 +1548,7 @@ pub(crate) enum ChildContainer {
     TraitId(TraitId),
     TraitAliasId(TraitAliasId),
     ImplId(ImplId),
+    ExternBlockId(ExternBlockId),
     EnumId(EnumId),
     VariantId(VariantId),
     TypeAliasId(TypeAliasId),
@@ -555,6 +1562,7 @@ impl_from! {
     TraitId,
     TraitAliasId,
     ImplId,
+    ExternBlockId,
     EnumId,
     VariantId,
     TypeAliasId,
@@ -570,12 +1578,126 @@ impl ChildContainer {
             ChildContainer::DefWithBodyId(it) => it.child_by_source(db, file_id),
             ChildContainer::ModuleId(it) => it.child_by_source(db, file_id),
             ChildContainer::TraitId(it) => it.child_by_source(db, file_id),
-            ChildContainer::TraitAliasId(_) => DynMap::default(),
+            ChildContainer::TraitAliasId(it) => GenericDefId::from(it).child_by_source(db, file_id),
             ChildContainer::ImplId(it) => it.child_by_source(db, file_id),
+            ChildContainer::ExternBlockId(it) => it.child_by_source(db, file_id),
             ChildContainer::EnumId(it) => it.child_by_source(db, file_id),
             ChildContainer::VariantId(it) => it.child_by_source(db, file_id),
-            ChildContainer::TypeAliasId(_) => DynMap::default(),
+            ChildContainer::TypeAliasId(it) => GenericDefId::from(it).child_by_source(db, file_id),
             ChildContainer::GenericDefId(it) => it.child_by_source(db, file_id),
         }
     }
+
+    /// Returns the file all children of the container are declared in, if there is only one.
+    ///
+    /// Fields, enum variants and generic parameters are declared right in the source of their
+    /// container. Modules, bodies, traits, impls and extern blocks can contain macro calls, so
+    /// their children can also be declared in the files the calls expand to.
+    fn fixed_file(self, db: &dyn HirDatabase) -> Option<HirFileId> {
+        let db = db.upcast();
+        match self {
+            ChildContainer::VariantId(it) => Some(it.file_id(db)),
+            ChildContainer::EnumId(it) => Some(it.lookup(db).id.file_id()),
+            ChildContainer::TraitAliasId(it) => Some(GenericDefId::from(it).file_id(db)),
+            ChildContainer::TypeAliasId(it) => Some(GenericDefId::from(it).file_id(db)),
+            ChildContainer::GenericDefId(it) => Some(it.file_id(db)),
+            ChildContainer::DefWithBodyId(_)
+            | ChildContainer::ModuleId(_)
+            | ChildContainer::TraitId(_)
+            | ChildContainer::ImplId(_)
+            | ChildContainer::ExternBlockId(_) => None,
+        }
+    }
+
+    fn resolver(self, db: &dyn HirDatabase) -> Resolver {
+        let db = db.upcast();
+        match self {
+            ChildContainer::DefWithBodyId(it) => it.resolver(db),
+            ChildContainer::ModuleId(it) => it.resolver(db),
+            ChildContainer::TraitId(it) => it.resolver(db),
+            ChildContainer::TraitAliasId(it) => it.resolver(db),
+            ChildContainer::ImplId(it) => it.resolver(db),
+            ChildContainer::ExternBlockId(it) => it.resolver(db),
+            ChildContainer::EnumId(it) => GenericDefId::from(AdtId::from(it)).resolver(db),
+            ChildContainer::VariantId(it) => it.resolver(db),
+            ChildContainer::TypeAliasId(it) => it.resolver(db),
+            ChildContainer::GenericDefId(it) => it.resolver(db),
+        }
+    }
+}
+
+/// Whether [`SourceToDefCtx::node_to_def`] resolves nodes of `kind` by looking them up in the
+/// child map of their container, see [`container_child_to_def`].
+fn is_container_child(kind: SyntaxKind) -> bool {
+    matches!(
+        kind,
+        SyntaxKind::STRUCT
+            | SyntaxKind::ENUM
+            | SyntaxKind::UNION
+            | SyntaxKind::TRAIT
+            | SyntaxKind::TRAIT_ALIAS
+            | SyntaxKind::IMPL
+            | SyntaxKind::TYPE_ALIAS
+            | SyntaxKind::CONST
+            | SyntaxKind::STATIC
+            | SyntaxKind::FN
+            | SyntaxKind::RECORD_FIELD
+            | SyntaxKind::TUPLE_FIELD
+            | SyntaxKind::VARIANT
+            | SyntaxKind::MACRO_RULES
+            | SyntaxKind::MACRO_DEF
+            | SyntaxKind::EXTERN_CRATE
+    )
+}
+
+/// Looks `node` up in `dyn_map`, the child map of its container. Gives the same result as
+/// [`SourceToDefCtx::node_to_def`] for the nodes [`is_container_child`] accepts.
+fn container_child_to_def(dyn_map: &DynMap, node: &SyntaxNode) -> Option<AnyDef> {
+    fn get<Ast: AstNode + 'static, ID: Copy + 'static>(
+        dyn_map: &DynMap,
+        key: Key<Ast, ID>,
+        node: &Ast,
+    ) -> Option<ID> {
+        dyn_map[key].get(&AstPtr::new(node)).copied()
+    }
+    match_ast! {
+        match node {
+            ast::Struct(it) => get(dyn_map, keys::STRUCT, &it).map(|it| Struct::from(it).into()),
+            ast::Enum(it) => get(dyn_map, keys::ENUM, &it).map(|it| Enum::from(it).into()),
+            ast::Union(it) => get(dyn_map, keys::UNION, &it).map(|it| Union::from(it).into()),
+            ast::Trait(it) => get(dyn_map, keys::TRAIT, &it).map(|it| Trait::from(it).into()),
+            ast::TraitAlias(it) => {
+                get(dyn_map, keys::TRAIT_ALIAS, &it).map(|it| TraitAlias::from(it).into())
+            },
+            ast::Impl(it) => get(dyn_map, keys::IMPL, &it).map(|it| Impl::from(it).into()),
+            ast::TypeAlias(it) => {
+                get(dyn_map, keys::TYPE_ALIAS, &it).map(|it| TypeAlias::from(it).into())
+            },
+            ast::Const(it) => get(dyn_map, keys::CONST, &it).map(|it| Const::from(it).into()),
+            ast::Static(it) => get(dyn_map, keys::STATIC, &it).map(|it| Static::from(it).into()),
+            ast::Fn(it) => match get(dyn_map, keys::FUNCTION, &it) {
+                Some(it) => Some(Function::from(it).into()),
+                None => get(dyn_map, keys::PROC_MACRO, &it).map(|it| Macro::from(MacroId::from(it)).into()),
+            },
+            ast::RecordField(it) => {
+                get(dyn_map, keys::RECORD_FIELD, &it).map(|it| Field::from(it).into())
+            },
+            ast::TupleField(it) => {
+                get(dyn_map, keys::TUPLE_FIELD, &it).map(|it| Field::from(it).into())
+            },
+            ast::Variant(it) => {
+                get(dyn_map, keys::ENUM_VARIANT, &it).map(|it| Variant::from(it).into())
+            },
+            ast::MacroRules(it) => {
+                get(dyn_map, keys::MACRO_RULES, &it).map(|it| Macro::from(MacroId::from(it)).into())
+            },
+            ast::MacroDef(it) => {
+                get(dyn_map, keys::MACRO2, &it).map(|it| Macro::from(MacroId::from(it)).into())
+            },
+            ast::ExternCrate(it) => {
+                get(dyn_map, keys::EXTERN_CRATE, &it).map(|it| ExternCrateDecl::from(it).into())
+            },
+            _ => None,
+        }
+    }
 }
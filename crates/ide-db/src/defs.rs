@@ -468,11 +468,12 @@ impl NameClass {
             sema: &Semantics<'_, RootDatabase>,
             ident_pat: ast::IdentPat,
         ) -> Option<NameClass> {
-            if let Some(def) = sema.resolve_bind_pat_to_const(&ident_pat) {
-                return Some(NameClass::ConstReference(Definition::from(def)));
-            }
-
-            let local = sema.to_def(&ident_pat)?;
+            let local = match sema.resolve_ident_pat(&ident_pat)? {
+                Either::Left(local) => local,
+                Either::Right(def) => {
+                    return Some(NameClass::ConstReference(Definition::from(def)))
+                }
+            };
             let pat_parent = ident_pat.syntax().parent();
             if let Some(record_pat_field) = pat_parent.and_then(ast::RecordPatField::cast) {
                 if record_pat_field.name_ref().is_none() {
@@ -491,9 +492,8 @@ impl NameClass {
             sema: &Semantics<'_, RootDatabase>,
             rename: ast::Rename,
         ) -> Option<Definition> {
-            if let Some(use_tree) = rename.syntax().parent().and_then(ast::UseTree::cast) {
-                let path = use_tree.path()?;
-                sema.resolve_path(&path).map(Definition::from)
+            if rename.syntax().parent().and_then(ast::UseTree::cast).is_some() {
+                sema.resolve_use_rename(&rename).map(Definition::from)
             } else {
                 sema.to_def(&rename.syntax().parent().and_then(ast::ExternCrate::cast)?)
                     .map(Definition::ExternCrateDecl)
@@ -44,6 +44,9 @@ pub mod syntax_helpers {
     pub use parser::LexedStr;
 }
 
+#[cfg(test)]
+mod tests;
+
 pub use hir::ChangeWithProcMacros;
 
 use std::{fmt, mem::ManuallyDrop};
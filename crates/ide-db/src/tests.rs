@@ -0,0 +1,60 @@
+//! Tests for the source-to-def and def-to-source mappings of `hir::Semantics`.
+//!
+//! `hir` has no database of its own to run fixtures against, so these live here.
+
+mod caches;
+mod def_to_src;
+mod source_to_def;
+
+use base_db::FileId;
+use hir::Semantics;
+use syntax::{
+    ast::{self, AstNode, HasName},
+    SyntaxNode,
+};
+use test_fixture::WithFixture;
+
+use crate::RootDatabase;
+
+/// Sets up `ra_fixture` as a single file and runs `check` against the syntax tree of that file.
+#[track_caller]
+fn check_sema(ra_fixture: &str, check: impl FnOnce(&Semantics<'_, RootDatabase>, &SyntaxNode)) {
+    let (db, file_id) = RootDatabase::with_single_file(ra_fixture);
+    let sema = Semantics::new(&db);
+    let file = sema.parse(file_id);
+    check(&sema, file.syntax());
+}
+
+/// Like [`check_sema`], but for a multi-file fixture. The files are passed in fixture order.
+#[track_caller]
+fn check_sema_many(
+    ra_fixture: &str,
+    check: impl FnOnce(&Semantics<'_, RootDatabase>, &[(FileId, ast::SourceFile)]),
+) {
+    let (db, file_ids) = RootDatabase::with_many_files(ra_fixture);
+    let sema = Semantics::new(&db);
+    let files: Vec<_> = file_ids.into_iter().map(|it| (it, sema.parse(it))).collect();
+    check(&sema, &files);
+}
+
+/// The first `N` in `root`, in preorder.
+#[track_caller]
+fn find<N: AstNode>(root: &SyntaxNode) -> N {
+    root.descendants()
+        .find_map(N::cast)
+        .unwrap_or_else(|| panic!("no `{}` in `{root}`", std::any::type_name::<N>()))
+}
+
+/// Every `N` in `root`, in preorder.
+fn find_all<N: AstNode>(root: &SyntaxNode) -> Vec<N> {
+    root.descendants().filter_map(N::cast).collect()
+}
+
+/// The first `N` in `root` that is called `name`.
+#[track_caller]
+fn find_named<N: AstNode + HasName>(root: &SyntaxNode, name: &str) -> N {
+    root.descendants()
+        .filter_map(N::cast)
+        .find(|it| it.name().map_or(false, |it| it.text() == name))
+        .unwrap_or_else(|| panic!("no `{}` named `{name}`", std::any::type_name::<N>()))
+}
@@ -0,0 +1,393 @@
+use std::{cell::RefCell, rc::Rc};
+
+use hir::Semantics;
+use syntax::ast::{self, AstNode, HasName};
+use test_fixture::WithFixture;
+
+use crate::RootDatabase;
+
+use super::{check_sema, check_sema_many, find, find_all, find_named};
+
+#[test]
+fn invalidate_file_drops_the_parsed_tree() {
+    let (db, file_id) = RootDatabase::with_single_file("struct S;");
+    let sema = Semantics::new(&db);
+    let file = sema.parse(file_id);
+    let strukt = sema.to_def(&find::<ast::Struct>(file.syntax())).unwrap();
+    let root = |src: hir::InFile<ast::Struct>| src.value.syntax().ancestors().last().unwrap();
+    assert_eq!(root(sema.source(strukt).unwrap()), *file.syntax());
+
+    // `sema` borrows the database, so the text can't change while it is alive. What matters is
+    // that the file is parsed again, which would pick up a new text.
+    sema.invalidate_file(file_id.into());
+    let reparsed = sema.parse(file_id);
+    assert_ne!(reparsed.syntax(), file.syntax());
+    assert_eq!(root(sema.source(strukt).unwrap()), *reparsed.syntax());
+}
+
+#[test]
+fn invalidate_all_empties_both_caches() {
+    let (db, file_id) = RootDatabase::with_single_file(
+        r#"
+struct S { f: u8 }
+enum E { A, B }
+"#,
+    );
+    let sema = Semantics::new(&db);
+    let file = sema.parse(file_id);
+    let resolve_all = || {
+        for field in find_all::<ast::RecordField>(file.syntax()) {
+            sema.to_def(&field).unwrap();
+        }
+        for variant in find_all::<ast::Variant>(file.syntax()) {
+            sema.to_def(&variant).unwrap();
+        }
+        sema.source_to_def_stats().misses
+    };
+    let strukt = sema.to_def(&find::<ast::Struct>(file.syntax())).unwrap();
+    let misses = resolve_all();
+    let src = sema.source(strukt).unwrap();
+    let epoch = sema.source_to_def_epoch();
+
+    sema.invalidate_all();
+    assert!(sema.source_to_def_epoch() > epoch);
+    // Every child map is gone, so resolving the same nodes builds all of them again.
+    assert_eq!(resolve_all(), 2 * misses);
+    assert_eq!(sema.source_to_def_rebuild_count(), misses);
+    // The cached tree is gone as well, so the source is looked up in a fresh parse.
+    assert_ne!(sema.source(strukt).unwrap().value.syntax(), src.value.syntax());
+}
+
+#[test]
+fn on_evict_fires_on_capacity_eviction() {
+    check_sema(
+        r#"
+struct A { a: u8 }
+struct B { b: u8 }
+"#,
+        |sema, file| {
+            let evicted = Rc::new(RefCell::new(Vec::new()));
+            sema.set_on_evict({
+                let evicted = evicted.clone();
+                move |entry| evicted.borrow_mut().push(*entry)
+            });
+            sema.set_source_to_def_capacity(Some(2));
+            let fields = find_all::<ast::RecordField>(file);
+
+            // The maps of the module and of `A` fit.
+            sema.to_def(&fields[0]).unwrap();
+            assert!(evicted.borrow().is_empty());
+
+            // The one of `B` doesn't, it replaces the one of `A`, which was used least recently.
+            sema.to_def(&fields[1]).unwrap();
+            assert_eq!(*evicted.borrow(), [hir::EvictedEntry { file_id: sema.hir_file_for(file) }]);
+            sema.to_def(&fields[0]).unwrap();
+            assert_eq!(evicted.borrow().len(), 2);
+            assert_eq!(sema.source_to_def_rebuild_count(), 1);
+
+            // Lifting the bound doesn't drop anything.
+            sema.set_source_to_def_capacity(None);
+            sema.to_def(&fields[1]).unwrap();
+            assert_eq!(evicted.borrow().len(), 2);
+        },
+    );
+}
+
+#[test]
+fn rebuild_count_after_invalidate_file() {
+    check_sema_many(
+        r#"
+//- /main.rs
+mod other;
+struct A { a: u8 }
+//- /other.rs
+struct B { b: u8 }
+"#,
+        |sema, files| {
+            let fields: Vec<_> =
+                files.iter().map(|(_, file)| find::<ast::RecordField>(file.syntax())).collect();
+            let resolve_all = || fields.iter().for_each(|it| assert!(sema.to_def(it).is_some()));
+            resolve_all();
+            assert_eq!(sema.source_to_def_rebuild_count(), 0);
+
+            // Only the maps of the invalidated file, for its module and `B`, are built again.
+            sema.invalidate_file(files[1].0.into());
+            resolve_all();
+            assert_eq!(sema.source_to_def_rebuild_count(), 2);
+            resolve_all();
+            assert_eq!(sema.source_to_def_rebuild_count(), 2);
+        },
+    );
+}
+
+#[test]
+fn container_cache_is_dropped_on_invalidation() {
+    check_sema(
+        r#"
+struct S { a: u8, b: u8 }
+"#,
+        |sema, file| {
+            let fields = find_all::<ast::RecordField>(file);
+            let resolve_all = || {
+                fields.iter().for_each(|it| assert!(sema.to_def(it).is_some()));
+                sema.source_to_def_stats().container_searches
+            };
+            let searches = resolve_all();
+            assert_eq!(resolve_all(), searches);
+
+            sema.invalidate_file(sema.hir_file_for(file));
+            assert_eq!(resolve_all(), 2 * searches);
+        },
+    );
+}
+
+#[test]
+fn container_cache_is_dropped_on_macro_toggle() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => { struct S; };
+}
+fn f() {
+    m!();
+}
+"#,
+        |sema, file| {
+            let expansion = sema.expand(&find(file)).unwrap();
+            let strukt = find::<ast::Struct>(&expansion);
+            // The struct is declared in the block of `f`, which is only found by climbing out of
+            // the expansion.
+            assert!(sema.to_def(&strukt).is_some());
+            let searches = sema.source_to_def_stats().container_searches;
+
+            sema.set_resolve_in_macro_expansions(false);
+            assert_eq!(sema.to_def(&strukt), None);
+            assert!(sema.source_to_def_stats().container_searches > searches);
+
+            sema.set_resolve_in_macro_expansions(true);
+            assert!(sema.to_def(&strukt).is_some());
+        },
+    );
+}
+
+#[test]
+fn resolve_no_cache_leaves_the_cache_alone() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => { struct S { f: u8 } };
+}
+fn f() {
+    m!();
+}
+struct T { g: u8 }
+#[cfg(never)]
+struct U;
+"#,
+        |sema, file| {
+            let expansion = sema.expand(&find(file)).unwrap();
+            let nodes = [
+                find::<ast::RecordField>(&expansion).syntax().clone(),
+                find_all::<ast::RecordField>(file)[0].syntax().clone(),
+                // Doesn't resolve, which `node_to_def` would remember.
+                find_named::<ast::Struct>(file, "U").syntax().clone(),
+            ];
+            let state = || {
+                (
+                    sema.source_to_def_stats(),
+                    sema.source_to_def_epoch(),
+                    sema.source_to_def_rebuild_count(),
+                )
+            };
+
+            let before = state();
+            let defs: Vec<_> = nodes.iter().map(|it| sema.resolve_no_cache(it)).collect();
+            assert_eq!(state(), before);
+            assert!(defs[0].is_some() && defs[1].is_some() && defs[2].is_none());
+
+            // The cached lookups agree, and still have to build everything themselves.
+            let cached: Vec<_> = nodes.iter().map(|it| sema.node_to_def(it)).collect();
+            assert_eq!(defs, cached);
+            assert!(sema.source_to_def_stats().misses > before.0.misses);
+            assert!(sema.source_to_def_stats().container_searches > before.0.container_searches);
+        },
+    );
+}
+
+#[test]
+fn invalidate_file_only_drops_that_files_entries() {
+    check_sema(
+        r#"
+macro_rules! m {
+    ($name:ident) => { struct $name { f: u8 } };
+}
+m!(A);
+m!(B);
+"#,
+        |sema, file| {
+            let fields: Vec<_> = find_all::<ast::MacroCall>(file)
+                .iter()
+                .map(|call| find::<ast::RecordField>(&sema.expand(call).unwrap()))
+                .collect();
+            let resolve = |idx: usize| assert!(sema.to_def(&fields[idx]).is_some());
+            resolve(0);
+            resolve(1);
+            let before = sema.source_to_def_stats();
+            assert_eq!(before.expansions, 2);
+
+            sema.invalidate_file(sema.hir_file_for(fields[0].syntax()));
+            // The maps and the expansion info of the other macro file survive.
+            resolve(1);
+            let after = sema.source_to_def_stats();
+            assert_eq!((after.misses, after.expansions), (before.misses, before.expansions));
+
+            resolve(0);
+            let after = sema.source_to_def_stats();
+            assert!(after.misses > before.misses);
+            assert_eq!(after.expansions, before.expansions + 1);
+        },
+    );
+}
+
+#[test]
+fn to_def_misses_are_remembered_until_invalidation() {
+    check_sema(
+        r#"
+struct S;
+#[cfg(never)]
+struct U;
+"#,
+        |sema, file| {
+            let disabled = find_all::<ast::Struct>(file).pop().unwrap();
+            assert_eq!(sema.to_def(&disabled), None);
+            let stats = sema.source_to_def_stats();
+
+            // Known to miss, so not even the child map is looked at again.
+            assert_eq!(sema.to_def(&disabled), None);
+            assert_eq!(sema.source_to_def_stats(), stats);
+
+            // Invalidation forgets the miss, so the lookup builds the map again.
+            sema.invalidate_file(sema.hir_file_for(file));
+            assert_eq!(sema.to_def(&disabled), None);
+            let after = sema.source_to_def_stats();
+            assert_eq!(after.misses, stats.misses + 1);
+            assert_eq!(sema.to_def(&disabled), None);
+            assert_eq!(sema.source_to_def_stats(), after);
+        },
+    );
+}
+
+#[test]
+fn file_modules_can_be_held_across_lookups() {
+    check_sema_many(
+        r#"
+//- /main.rs crate:main
+mod m;
+//- /m.rs
+struct S;
+"#,
+        |sema, files| {
+            let (file_id, file) = &files[1];
+            let strukt = find::<ast::Struct>(file.syntax());
+            let mut modules = 0;
+            // The modules are owned by the iterator, so the cache is free to change meanwhile.
+            for module in sema.file_to_module_defs(*file_id) {
+                sema.invalidate_all();
+                let def = sema.to_def(&strukt).unwrap();
+                assert_eq!(def.module(sema.db), module);
+                modules += 1;
+            }
+            assert_eq!(modules, 1);
+        },
+    );
+}
+
+#[test]
+fn derives_resolve_from_the_prewarmed_cache() {
+    check_sema(
+        r#"
+//- minicore: derive, clone
+#[derive(Clone)]
+struct A;
+mod m {
+    #[derive(Clone)]
+    pub struct B;
+    #[derive(Clone)]
+    pub enum C {
+        X,
+    }
+}
+struct Plain;
+"#,
+        |sema, file| {
+            sema.prewarm_derives(sema.hir_file_for(file));
+            let warm = sema.source_to_def_stats();
+
+            let (plain, derived) = find_all::<ast::Adt>(file)
+                .into_iter()
+                .partition::<Vec<_>, _>(|it| it.name().unwrap().text() == "Plain");
+            assert_eq!(derived.len(), 3);
+            for adt in &derived {
+                assert!(sema.is_derive_annotated(adt));
+                let attr = find::<ast::Attr>(adt.syntax());
+                let macros = sema.resolve_derive_macro(&attr).unwrap();
+                assert!(matches!(macros[..], [Some(_)]), "{adt}");
+            }
+            let after = sema.source_to_def_stats();
+            assert_eq!(
+                (after.misses, after.container_searches),
+                (warm.misses, warm.container_searches)
+            );
+            assert!(after.hits > warm.hits);
+
+            // `Plain` has no attributes, so it was skipped by the prewarming.
+            assert!(!sema.is_derive_annotated(&plain[0]));
+            assert!(sema.source_to_def_stats().container_searches > warm.container_searches);
+        },
+    );
+}
+
+#[test]
+fn prewarm_file_builds_all_container_maps() {
+    check_sema(
+        r#"
+struct S {
+    f: u32,
+}
+enum E {
+    A,
+}
+trait Tr {
+    fn m(&self);
+}
+impl Tr for S {
+    fn m(&self) {}
+}
+impl S {
+    fn new() {}
+}
+mod inline {
+    struct T(u8);
+    impl T {
+        const C: () = ();
+    }
+}
+"#,
+        |sema, file| {
+            sema.prewarm_file(sema.hir_file_for(file).file_id().unwrap());
+            let warm = sema.source_to_def_stats();
+
+            // Everything below lives in a module, adt, trait or impl whose map was built above.
+            assert!(sema.to_def(&find::<ast::RecordField>(file)).is_some());
+            assert!(sema.to_def(&find::<ast::TupleField>(file)).is_some());
+            assert!(sema.to_def(&find::<ast::Variant>(file)).is_some());
+            assert!(sema.to_def(&find::<ast::Const>(file)).is_some());
+            for func in find_all::<ast::Fn>(file) {
+                assert!(sema.to_def(&func).is_some(), "{func}");
+            }
+            let after = sema.source_to_def_stats();
+            assert_eq!(after.misses, warm.misses);
+            assert!(after.hits > warm.hits);
+        },
+    );
+}
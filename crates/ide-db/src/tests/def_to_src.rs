@@ -0,0 +1,521 @@
+use either::Either;
+use hir::{HasSource, InFile, PathResolution, Semantics};
+use syntax::ast::{self, AstNode, HasName};
+use test_fixture::WithFixture;
+
+use crate::RootDatabase;
+
+use super::{check_sema, check_sema_many, find, find_all, find_named};
+
+#[test]
+fn variant_field_sources_are_in_declaration_order() {
+    check_sema(
+        r#"
+enum E {
+    Unit,
+    Tuple(u8, u16),
+    Record { c: u8, a: u16, b: u32 },
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let field_names = |name: &str| {
+                let variant = sema.to_def(&find_named::<ast::Variant>(file, name)).unwrap();
+                variant
+                    .field_sources(sema)
+                    .into_iter()
+                    .map(|(field, source)| {
+                        assert_eq!(sema.source(field).unwrap().value, source);
+                        field.name(db).display(db).to_string()
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            assert!(field_names("Unit").is_empty());
+            assert_eq!(field_names("Tuple"), ["0", "1"]);
+            assert_eq!(field_names("Record"), ["c", "a", "b"]);
+        },
+    );
+}
+
+#[test]
+fn macro_expansion_is_shared_between_source_and_to_def() {
+    let (db, file_id) = RootDatabase::with_single_file(
+        r#"
+macro_rules! m {
+    () => { struct S; };
+}
+m!();
+"#,
+    );
+    let sema = Semantics::new(&db);
+    let module = sema.file_to_module_def(file_id).unwrap();
+    let strukt = module
+        .declarations(&db)
+        .into_iter()
+        .find_map(|def| match def {
+            hir::ModuleDef::Adt(hir::Adt::Struct(it)) => Some(it),
+            _ => None,
+        })
+        .unwrap();
+
+    let src = sema.source(strukt).unwrap();
+    assert_eq!(sema.to_def(&src.value), Some(strukt));
+    assert_eq!(sema.source_to_def_stats().expansions, 1);
+
+    // Resolving back computed the expansion, which the next source lookup reuses.
+    let macro_file = src.file_id.macro_file().unwrap();
+    let src = sema.source(strukt).unwrap();
+    assert_eq!(
+        src.value.syntax().ancestors().last().unwrap(),
+        sema.expand_and_map(macro_file).value
+    );
+    assert_eq!(sema.to_def(&src.value), Some(strukt));
+    assert_eq!(sema.source_to_def_stats().expansions, 1);
+}
+
+#[test]
+fn self_param_source_of_bodyless_trait_method() {
+    check_sema(
+        r#"
+trait Tr {
+    fn method(&self, x: u32);
+}
+"#,
+        |sema, file| {
+            let func = find::<ast::Fn>(file);
+            assert!(func.body().is_none());
+            let self_param = sema.to_def(&func).unwrap().self_param(sema.db).unwrap();
+
+            let src = self_param.source(sema.db).unwrap();
+            assert_eq!(src.file_id, sema.hir_file_for(file));
+            assert_eq!(src.value.syntax().text().to_string(), "&self");
+            assert_eq!(sema.source(self_param).unwrap().value.syntax().text().to_string(), "&self");
+        },
+    );
+}
+
+#[test]
+fn trait_self_param_source() {
+    check_sema(
+        r#"
+trait Tr {
+    fn f(&self) -> Self;
+}
+"#,
+        |sema, file| {
+            let trait_ = find::<ast::Trait>(file);
+            let self_ty = find_all::<ast::Path>(file).pop().unwrap();
+            assert_eq!(self_ty.to_string(), "Self");
+
+            // `Self` in the signature resolves to the implicit parameter, whose source is the
+            // trait.
+            let Some(PathResolution::TypeParam(param)) = sema.resolve_path(&self_ty) else {
+                panic!("`Self` doesn't resolve to a type parameter")
+            };
+            assert!(param.is_implicit(sema.db));
+            let src = sema.source(param.merge()).unwrap();
+            assert!(
+                matches!(src.value, Either::Right(ast::TraitOrAlias::Trait(it)) if it == trait_)
+            );
+
+            let def = sema.to_def(&trait_).unwrap();
+            assert_eq!(def.self_param_source(sema).unwrap().value, trait_);
+        },
+    );
+}
+
+#[test]
+fn impl_trait_and_self_ty_sources() {
+    check_sema(
+        r#"
+trait Tr<T> {}
+struct S;
+impl Tr<u8> for S {}
+impl S {}
+"#,
+        |sema, file| {
+            let impls: Vec<_> =
+                find_all::<ast::Impl>(file).iter().map(|it| sema.to_def(it).unwrap()).collect();
+            let text = |src: Option<InFile<ast::Type>>| src.map(|it| it.value.to_string());
+
+            assert_eq!(text(impls[0].trait_source(sema)).as_deref(), Some("Tr<u8>"));
+            assert_eq!(text(impls[0].self_ty_source(sema)).as_deref(), Some("S"));
+            assert_eq!(text(impls[1].trait_source(sema)), None);
+            assert_eq!(text(impls[1].self_ty_source(sema)).as_deref(), Some("S"));
+        },
+    );
+}
+
+#[test]
+fn variant_discriminant_sources() {
+    check_sema(
+        r#"
+enum E {
+    A = 1 + 2,
+    B,
+}
+"#,
+        |sema, file| {
+            let variants: Vec<_> =
+                find_all::<ast::Variant>(file).iter().map(|it| sema.to_def(it).unwrap()).collect();
+
+            let discriminant = variants[0].discriminant_source(sema).unwrap();
+            assert_eq!(discriminant.value.to_string(), "1 + 2");
+            assert_eq!(
+                sema.resolve_discriminant(&discriminant.value),
+                Some(hir::DefWithBody::Variant(variants[0]))
+            );
+            assert!(variants[1].discriminant_source(sema).is_none());
+        },
+    );
+}
+
+#[test]
+fn trait_alias_bound_sources() {
+    check_sema(
+        r#"
+trait Clone {}
+trait Debug {}
+trait Alias = Clone + Debug + 'static;
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let alias = sema.to_def(&find::<ast::TraitAlias>(file)).unwrap();
+
+            let bounds = alias.bound_sources(sema);
+            assert_eq!(bounds.len(), 2);
+            let resolved: Vec<_> = bounds
+                .iter()
+                .map(|bound| {
+                    let ast::Type::PathType(ty) = &bound.value else {
+                        panic!("not a path: {bound:?}")
+                    };
+                    match sema.resolve_path(&ty.path().unwrap()) {
+                        Some(PathResolution::Def(hir::ModuleDef::Trait(it))) => {
+                            it.name(db).display(db).to_string()
+                        }
+                        res => panic!("unexpected resolution {res:?}"),
+                    }
+                })
+                .collect();
+            assert_eq!(resolved, ["Clone", "Debug"]);
+        },
+    );
+}
+
+#[test]
+fn expansion_info_is_computed_once() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => { struct S; };
+}
+m!();
+"#,
+        |sema, file| {
+            let macro_file = sema.to_def(&find::<ast::MacroCall>(file)).unwrap().as_macro_file();
+            assert_eq!(sema.source_to_def_stats().expansions, 0);
+
+            let info = sema.expansion_info(macro_file);
+            assert_eq!(sema.source_to_def_stats().expansions, 1);
+            let first = info.expanded().value;
+            let second = sema.expansion_info(macro_file).expanded().value;
+            assert_eq!(sema.source_to_def_stats().expansions, 1);
+            assert_eq!(first, second);
+
+            // The expansion is known to `sema`, so its items resolve, even while the info is
+            // still around.
+            assert!(sema.to_def(&find::<ast::Struct>(&first)).is_some());
+            assert_eq!(info.call_file(), sema.hir_file_for(file));
+        },
+    );
+}
+
+#[test]
+fn macro_call_to_expanded_file() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => { struct S; };
+}
+m!();
+"#,
+        |sema, file| {
+            let call = sema.to_def(&find::<ast::MacroCall>(file)).unwrap();
+            let macro_file = sema.macro_call_to_expanded_file(call);
+            assert_eq!(macro_file, call.as_macro_file());
+            assert_eq!(sema.source_to_def_stats().expansions, 1);
+
+            let expanded = sema.parse_or_expand(macro_file.into());
+            assert_eq!(find::<ast::Struct>(&expanded).name().unwrap().text(), "S");
+            assert_eq!(expanded, sema.expansion_info(macro_file).expanded().value);
+            assert_eq!(sema.macro_call_to_expanded_file(call), macro_file);
+            assert_eq!(sema.source_to_def_stats().expansions, 1);
+        },
+    );
+}
+
+#[test]
+fn derive_helper_sources() {
+    check_sema(
+        r#"
+//- minicore: derive, default
+#[derive(Default)]
+enum E {
+    #[default]
+    A,
+    B,
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let path = find_all::<ast::Path>(file)
+                .into_iter()
+                .find(|it| it.syntax().text() == "default")
+                .unwrap();
+            let Some(PathResolution::DeriveHelper(helper)) = sema.resolve_path(&path) else {
+                panic!("`default` doesn't resolve to a derive helper");
+            };
+            assert_eq!(helper.name(db).display(db).to_string(), "default");
+
+            let src = helper.source(db).unwrap();
+            let derive = helper.derive().source(db).unwrap();
+            assert_eq!(src.file_id, derive.file_id);
+            assert_eq!(src.value.simple_name().as_deref(), Some("rustc_builtin_macro"));
+            assert!(
+                src.value.syntax().to_string().contains("attributes(default)"),
+                "{}",
+                src.value
+            );
+        },
+    );
+}
+
+#[test]
+fn sources_share_the_parsed_file() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => {};
+}
+enum E {
+    V,
+}
+fn f() {}
+const C: () = ();
+static S: () = ();
+trait Tr {}
+type T = ();
+"#,
+        |sema, file| {
+            // The sources are looked up in the tree `sema` already parsed the file into.
+            macro_rules! check_source {
+                ($node:expr) => {{
+                    let node = $node;
+                    let def = sema.to_def(&node).unwrap();
+                    let src = sema.source(def).unwrap();
+                    assert_eq!(src.file_id, sema.hir_file_for(file));
+                    assert_eq!(src.value.syntax(), node.syntax());
+                }};
+            }
+            check_source!(find::<ast::Variant>(file));
+            check_source!(find::<ast::Fn>(file));
+            check_source!(find::<ast::Const>(file));
+            check_source!(find::<ast::Static>(file));
+            check_source!(find::<ast::Trait>(file));
+            check_source!(find::<ast::TypeAlias>(file));
+
+            let mac = sema.to_def(&find::<ast::Macro>(file)).unwrap();
+            let src = sema.source(mac).unwrap();
+            assert!(
+                matches!(src.value, Either::Left(it) if it.syntax().parent().as_ref() == Some(file))
+            );
+        },
+    );
+}
+
+#[test]
+fn builtin_types_have_no_source() {
+    check_sema(
+        r#"
+fn f(_: u32, _: str) {}
+"#,
+        |sema, file| {
+            let builtins = find_all::<ast::Path>(file)
+                .iter()
+                .map(|path| match sema.resolve_path(path) {
+                    Some(PathResolution::BuiltinType(it)) => it,
+                    res => panic!("unexpected resolution of `{path}`: {res:?}"),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(builtins.len(), 2);
+            for builtin in builtins {
+                assert!(builtin.source(sema.db).is_none());
+                assert!(sema.source(builtin).is_none());
+            }
+        },
+    );
+}
+
+#[test]
+fn crate_root_sources() {
+    check_sema_many(
+        r#"
+//- /main.rs crate:main deps:dep
+mod a;
+//- /a.rs
+fn f() {}
+//- /lib.rs crate:dep
+pub struct S;
+"#,
+        |sema, files| {
+            let root_of = |idx: usize| {
+                let krate = sema.file_to_module_def(files[idx].0).unwrap().krate();
+                let root = krate.root_source(sema.db).unwrap();
+                (root.file_id.file_id().unwrap(), root.value.syntax().text().to_string())
+            };
+            let file = |idx: usize| {
+                let (file_id, file) = &files[idx];
+                (*file_id, file.syntax().text().to_string())
+            };
+
+            assert_eq!(root_of(0), file(0));
+            assert_eq!(root_of(1), file(0));
+            assert_eq!(root_of(2), file(2));
+        },
+    );
+}
+
+#[test]
+fn tool_module_sources() {
+    check_sema(
+        r#"
+#![register_tool(my_tool)]
+
+#[my_tool::attr]
+#[rustfmt::skip]
+fn f() {}
+"#,
+        |sema, file| {
+            let sources: Vec<_> = find_all::<ast::Attr>(file)
+                .iter()
+                .filter(|attr| attr.kind() == ast::AttrKind::Outer)
+                .map(|attr| {
+                    let Some(hir::AnyDef::ToolModule(tool)) = sema.resolve_attr_path(attr) else {
+                        panic!("`{attr}` doesn't resolve to a tool")
+                    };
+                    sema.source(tool).map(|it| it.value.to_string())
+                })
+                .collect();
+
+            // `rustfmt` is known to the compiler and isn't registered anywhere.
+            assert_eq!(sources, [Some("#![register_tool(my_tool)]".to_owned()), None]);
+        },
+    );
+}
+
+#[test]
+fn benchmark_generic_param_sources() {
+    if test_utils::skip_slow_tests() {
+        return;
+    }
+    let lifetimes = (0..200).map(|i| format!("'l{i}")).collect::<Vec<_>>().join(", ");
+    let types = (0..200).map(|i| format!("T{i}")).collect::<Vec<_>>().join(", ");
+    let (db, file_id) =
+        RootDatabase::with_single_file(&format!("fn f<{lifetimes}, {types}>() {{}}"));
+    let sema = Semantics::new(&db);
+    let file = sema.parse(file_id);
+    let func = sema.to_def(&find::<ast::Fn>(file.syntax())).unwrap();
+    let params = hir::GenericDef::from(func).params(&db);
+
+    let _b = test_utils::bench("generic param sources");
+    let sources = params
+        .into_iter()
+        .filter_map(|param| match param {
+            hir::GenericParam::LifetimeParam(it) => sema.source(it).map(|_| ()),
+            hir::GenericParam::TypeParam(it) => sema.source(it.merge()).map(|_| ()),
+            hir::GenericParam::ConstParam(it) => sema.source(it.merge()).map(|_| ()),
+        })
+        .count();
+    assert_eq!(sources, 400);
+}
+
+#[test]
+fn param_sources_of_macro_emitted_closures() {
+    check_sema(
+        r#"
+macro_rules! closure {
+    () => { |a: u32, b: u32| a + b };
+}
+macro_rules! id {
+    ($($t:tt)*) => { $($t)* };
+}
+fn f() {
+    let from_def = closure!();
+    let from_call = id!(|c: u8| c);
+    from_def(1, 2);
+    from_call(3);
+}
+"#,
+        |sema, file| {
+            let param_sources = |callee: &str| {
+                let call = find_all::<ast::CallExpr>(file)
+                    .into_iter()
+                    .find(|it| it.expr().unwrap().to_string() == callee)
+                    .unwrap();
+                let ty = sema.type_of_expr(&call.expr().unwrap()).unwrap().original;
+                ty.as_callable(sema.db)
+                    .unwrap()
+                    .params()
+                    .into_iter()
+                    .map(|param| {
+                        let src = param.source(sema.db).unwrap();
+                        assert!(src.file_id.is_macro());
+                        src.value.right().unwrap().to_string()
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            assert_eq!(param_sources("from_def"), ["a: u32", "b: u32"]);
+            assert_eq!(param_sources("from_call"), ["c: u8"]);
+        },
+    );
+}
+
+#[test]
+fn param_sources_of_constructors_and_fn_pointers() {
+    check_sema(
+        r#"
+struct S(u8, u16);
+fn f(p: fn(u32)) {
+    S(1, 2);
+    p(3);
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let params = |call: &ast::CallExpr| {
+                let callable = sema.resolve_expr_as_callable(&call.expr().unwrap()).unwrap();
+                callable.params()
+            };
+            let calls = find_all::<ast::CallExpr>(file);
+
+            // Constructor parameters have no syntax of their own, but map to the tuple fields.
+            let fields: Vec<_> = params(&calls[0])
+                .into_iter()
+                .map(|param| {
+                    assert!(param.source(db).is_none());
+                    let field = sema.source(param.as_tuple_field(db).unwrap()).unwrap();
+                    field.value.syntax().to_string()
+                })
+                .collect();
+            assert_eq!(fields, ["u8", "u16"]);
+
+            let params = params(&calls[1]);
+            assert_eq!(params.len(), 1);
+            assert!(params[0].source(db).is_none());
+            assert_eq!(params[0].as_tuple_field(db), None);
+        },
+    );
+}
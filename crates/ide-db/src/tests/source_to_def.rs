@@ -0,0 +1,1496 @@
+use std::collections::BTreeMap;
+
+use base_db::SourceDatabaseExt2 as _;
+use either::Either;
+use hir::{
+    AnyDef, HasContainer, HasSource, InFile, ItemResolution, PathResolution, ResolveOutcome,
+    Semantics,
+};
+use syntax::{
+    ast::{self, AstNode, HasName},
+    match_ast, SyntaxNode, SyntaxNodePtr, TextRange, TextSize,
+};
+use test_fixture::WithFixture;
+
+use crate::{defs::NameClass, RootDatabase};
+
+use super::{check_sema, check_sema_many, find, find_all, find_named};
+
+#[test]
+fn classifying_names_reuses_child_maps() {
+    check_sema(
+        r#"
+struct A;
+struct B;
+struct C;
+struct D;
+enum E { V1, V2, V3 }
+fn f1() {}
+fn f2() {}
+fn f3() {}
+impl A {
+    fn m1(&self) {}
+    fn m2(&self) {}
+    fn m3(&self) {}
+    fn m4(&self) {}
+}
+"#,
+        |sema, file| {
+            let names = find_all::<ast::Name>(file);
+            for name in &names {
+                assert!(NameClass::classify(sema, name).is_some(), "unresolved name `{name}`");
+            }
+
+            let stats = sema.source_to_def_stats();
+            assert!(stats.hits + stats.misses >= names.len(), "{stats:?}");
+            // One child map each for the module, the enum and the impl, everything else should
+            // be served from the cache.
+            assert!(stats.hit_ratio() > 0.75, "{stats:?}");
+        },
+    );
+}
+
+#[test]
+fn derive_expansion_items_resolve_in_the_adts_module() {
+    check_sema(
+        r#"
+//- minicore: derive, clone
+mod m {
+    #[derive(Clone)]
+    pub struct S;
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let strukt = sema.to_def(&find::<ast::Struct>(file)).unwrap();
+            let expansion = sema.expand_derive_macro(&find(file)).unwrap().pop().unwrap();
+
+            let impl_ = sema.to_def(&find::<ast::Impl>(&expansion)).unwrap();
+            assert_eq!(impl_.module(db), strukt.module(db));
+            assert_eq!(impl_.self_ty(db).as_adt(), Some(strukt.into()));
+
+            let self_path = find_all::<ast::Path>(&expansion)
+                .into_iter()
+                .find(|it| it.syntax().text() == "Self")
+                .unwrap();
+            match sema.resolve_path(&self_path) {
+                Some(PathResolution::SelfType(it)) => assert_eq!(it, impl_),
+                res => panic!("unexpected resolution: {res:?}"),
+            }
+        },
+    );
+}
+
+#[test]
+fn node_to_def_with_range_focuses_the_name() {
+    check_sema(
+        r#"
+struct S;
+impl S {
+    pub fn method(&self) {}
+}
+"#,
+        |sema, file| {
+            let text = file.text().to_string();
+
+            let func = find::<ast::Fn>(file);
+            let (def, range) = sema.node_to_def_with_range(func.syntax()).unwrap();
+            assert_eq!(def, AnyDef::Function(sema.to_def(&func).unwrap()));
+            assert_eq!(range.file_id, sema.hir_file_for(file));
+            assert_eq!(&text[range.value], "method");
+
+            let impl_ = find::<ast::Impl>(file);
+            let (def, range) = sema.node_to_def_with_range(impl_.syntax()).unwrap();
+            assert_eq!(def, AnyDef::Impl(sema.to_def(&impl_).unwrap()));
+            assert_eq!(range.value, impl_.syntax().text_range());
+        },
+    );
+}
+
+#[test]
+fn qualified_macro_call_segments() {
+    check_sema(
+        r#"
+mod foo {
+    macro_rules! bar { () => {} }
+    pub(crate) use bar;
+}
+fn f() {
+    foo::bar!();
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let call = find::<ast::MacroCall>(file);
+            let path = call.path().unwrap();
+            let resolve = |segment: ast::PathSegment| {
+                sema.resolve_macro_call_path_segment(&call, &segment).map(|def| match def {
+                    AnyDef::Module(it) => format!("mod {}", it.name(db).unwrap().display(db)),
+                    AnyDef::Macro(it) => format!("macro {}", it.name(db).display(db)),
+                    def => panic!("unexpected definition: {def:?}"),
+                })
+            };
+
+            let qualifier = path.qualifier().unwrap().segment().unwrap();
+            assert_eq!(resolve(qualifier).as_deref(), Some("mod foo"));
+            assert_eq!(resolve(path.segment().unwrap()).as_deref(), Some("macro bar"));
+        },
+    );
+}
+
+#[test]
+fn resolve_in_macro_expansions_toggle() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => { 92 };
+}
+fn outer() -> u32 {
+    m!()
+}
+"#,
+        |sema, file| {
+            let outer = sema.to_def(&find::<ast::Fn>(file));
+            let expansion = sema.expand(&find(file)).unwrap();
+            let token = expansion.descendants_with_tokens().find_map(|it| it.into_token()).unwrap();
+            assert_eq!(token.text(), "92");
+
+            // By default, the expanded literal resolves in the context of the call.
+            assert_eq!(sema.token_to_def(&token), outer.map(AnyDef::Function));
+
+            // Treated as opaque, there is nothing to resolve to at the call.
+            sema.set_resolve_in_macro_expansions(false);
+            assert_eq!(sema.token_to_def(&token), None);
+
+            sema.set_resolve_in_macro_expansions(true);
+            assert_eq!(sema.token_to_def(&token), outer.map(AnyDef::Function));
+        },
+    );
+}
+
+#[test]
+fn node_to_def_detailed_alternatives() {
+    check_sema_many(
+        r#"
+//- /a.rs crate:a
+#[path = "shared.rs"]
+mod shared;
+struct Unique;
+//- /b.rs crate:b
+#[path = "shared.rs"]
+mod shared;
+//- /shared.rs
+pub struct S;
+"#,
+        |sema, files| {
+            let db = sema.db;
+            let shared = find::<ast::Struct>(files[2].1.syntax());
+            let res = sema.node_to_def_detailed(shared.syntax()).unwrap();
+            assert_eq!(Some(res.primary), sema.node_to_def(shared.syntax()));
+            assert_eq!(res.alternatives.len(), 1);
+            let krate = |def| match def {
+                AnyDef::Adt(it) => it.module(db).krate(),
+                def => panic!("unexpected definition: {def:?}"),
+            };
+            assert_ne!(krate(res.primary), krate(res.alternatives[0]));
+
+            let unique = find::<ast::Struct>(files[0].1.syntax());
+            let res = sema.node_to_def_detailed(unique.syntax()).unwrap();
+            assert_eq!(Some(res.primary), sema.node_to_def(unique.syntax()));
+            assert!(res.alternatives.is_empty());
+        },
+    );
+}
+
+#[test]
+fn variant_discriminants() {
+    check_sema(
+        r#"
+enum E {
+    A = 1 + 2,
+    B,
+}
+"#,
+        |sema, file| {
+            let variant = sema.to_def(&find_named::<ast::Variant>(file, "A")).unwrap();
+            let discriminant = find::<ast::Variant>(file).expr().unwrap();
+            assert_eq!(
+                sema.resolve_discriminant(&discriminant),
+                Some(hir::DefWithBody::Variant(variant))
+            );
+
+            // Sub-expressions of the discriminant aren't the discriminant itself.
+            let literal =
+                discriminant.syntax().descendants().skip(1).find_map(ast::Expr::cast).unwrap();
+            assert_eq!(sema.resolve_discriminant(&literal), None);
+        },
+    );
+}
+
+#[test]
+fn fields_with_default_values() {
+    check_sema(
+        r#"
+struct S { x: u32 = 5, y: u32 }
+"#,
+        |sema, file| {
+            // The parser doesn't support default values yet, so there is no expression that could
+            // resolve to a body. Once there is, it needs an owner in `DefWithBodyId`.
+            assert!(find_all::<ast::Expr>(file).is_empty());
+            assert!(file.descendants().any(|it| it.kind() == syntax::SyntaxKind::ERROR));
+
+            let fields: Vec<_> = find_all::<ast::RecordField>(file)
+                .iter()
+                .map(|it| sema.to_def(it).map(|it| it.name(sema.db).display(sema.db).to_string()))
+                .collect();
+            assert_eq!(fields, [Some("x".to_owned()), Some("y".to_owned())]);
+        },
+    );
+}
+
+#[test]
+fn macro_generated_foreign_items_to_def() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => {
+        fn generated();
+        static S: u8;
+    };
+}
+extern "C" {
+    m!();
+    fn direct();
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let block = sema.to_def(&find::<ast::ExternBlock>(file)).unwrap();
+            let direct = sema.to_def(&find::<ast::Fn>(file)).unwrap();
+            assert_eq!(direct.container(db), hir::ItemContainer::ExternBlock(block));
+
+            let expansion = sema.expand(&find::<ast::MacroCall>(file)).unwrap();
+            let generated = sema.to_def(&find::<ast::Fn>(&expansion)).unwrap();
+            assert_eq!(generated.name(db).display(db).to_string(), "generated");
+            assert_eq!(generated.container(db), hir::ItemContainer::ExternBlock(block));
+            let static_ = sema.to_def(&find::<ast::Static>(&expansion)).unwrap();
+            assert_eq!(static_.name(db).display(db).to_string(), "S");
+        },
+    );
+}
+
+#[test]
+fn extern_abis() {
+    check_sema(
+        r#"
+extern "C" {
+    fn c();
+}
+extern "Rust" {
+    fn rust();
+}
+extern {
+    fn default();
+}
+extern "C" fn c_fn() {}
+fn plain() {}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let blocks: Vec<_> = find_all::<ast::ExternBlock>(file)
+                .iter()
+                .map(|it| sema.to_def(it).unwrap().abi(db))
+                .collect();
+            assert_eq!(blocks, [Some("C".into()), Some("Rust".into()), Some("C".into())]);
+
+            let fns: Vec<_> = find_all::<ast::Fn>(file)
+                .iter()
+                .map(|it| {
+                    let func = sema.to_def(it).unwrap();
+                    if let hir::ItemContainer::ExternBlock(block) = func.container(db) {
+                        assert_eq!(func.abi(db), block.abi(db));
+                    }
+                    func.abi(db)
+                })
+                .collect();
+            assert_eq!(
+                fns,
+                [Some("C".into()), Some("Rust".into()), Some("C".into()), Some("C".into()), None]
+            );
+        },
+    );
+}
+
+#[test]
+fn field_sources_to_def() {
+    check_sema(
+        r#"
+struct Tuple(u8, u16);
+struct Record { a: u8, b: u16 }
+"#,
+        |sema, file| {
+            let tuple_fields = find_all::<ast::TupleField>(file);
+            let record_fields = find_all::<ast::RecordField>(file);
+            let sources = tuple_fields
+                .iter()
+                .map(|it| (hir::FieldSource::Pos(it.clone()), sema.to_def(it)))
+                .chain(
+                    record_fields
+                        .iter()
+                        .map(|it| (hir::FieldSource::Named(it.clone()), sema.to_def(it))),
+                );
+
+            for (source, field) in sources {
+                assert!(field.is_some());
+                assert_eq!(sema.to_def(&source), field);
+            }
+        },
+    );
+}
+
+#[test]
+fn record_expr_and_pat_fields_to_def() {
+    check_sema(
+        r#"
+struct S { x: u8, y: u8 }
+fn f(y: u8) {
+    let s = S { x: 0, y };
+    let S { x: _, y } = s;
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let decls = find_all::<ast::RecordField>(file);
+            let [x, y] = &decls[..] else { unreachable!() };
+            let (x, y) = (sema.to_def(x), sema.to_def(y));
+            assert!(x.is_some() && y.is_some());
+
+            // Both the explicit and the shorthand form resolve to the field, not to the binding.
+            let exprs: Vec<_> =
+                find_all::<ast::RecordExprField>(file).iter().map(|it| sema.to_def(it)).collect();
+            assert_eq!(exprs, [x, y]);
+            let pats: Vec<_> =
+                find_all::<ast::RecordPatField>(file).iter().map(|it| sema.to_def(it)).collect();
+            assert_eq!(pats, [x, y]);
+            assert_eq!(y.unwrap().name(db).display(db).to_string(), "y");
+        },
+    );
+}
+
+#[test]
+fn argument_position_impl_trait_to_def() {
+    check_sema(
+        r#"
+trait Clone {}
+trait Copy {}
+fn f(x: impl Clone, y: impl Copy) {}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let params: Vec<_> = find_all::<ast::ImplTraitType>(file)
+                .iter()
+                .map(|it| sema.to_def(it).unwrap())
+                .collect();
+            assert_eq!(params.len(), 2);
+            assert_ne!(params[0], params[1]);
+
+            let func = sema.to_def(&find::<ast::Fn>(file)).unwrap();
+            let generics = hir::GenericDef::from(func).type_or_const_params(db);
+            for param in params {
+                assert!(param.is_implicit(db));
+                assert!(generics.contains(&param.merge()));
+            }
+        },
+    );
+}
+
+#[test]
+fn block_modules() {
+    check_sema(
+        r#"
+fn f() {
+    {}
+    {
+        mod inner {}
+    }
+    {
+        let x = 1;
+    }
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let blocks: Vec<_> =
+                find_all::<ast::BlockExpr>(file).iter().map(|it| sema.block_module(it)).collect();
+            // Only the block declaring `inner` has items, the body of `f` merely contains blocks.
+            let [None, None, Some(block_module), None] = blocks[..] else {
+                panic!("unexpected block modules {blocks:?}")
+            };
+
+            let inner = sema.to_def(&find::<ast::Module>(file)).unwrap();
+            assert_eq!(inner.parent(db), Some(block_module));
+        },
+    );
+}
+
+#[test]
+fn lifetime_usages_to_def() {
+    check_sema(
+        r#"
+struct S<'a, 'b>
+where
+    'b: 'a,
+{
+    f: &'a u8,
+    g: &'static u8,
+    h: &'b u8,
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let params = find_all::<ast::LifetimeParam>(file);
+            let [a, b] = &params[..] else { unreachable!() };
+            let (a, b) = (sema.to_def(a).unwrap(), sema.to_def(b).unwrap());
+
+            let resolved: Vec<_> =
+                find_all::<ast::Lifetime>(file).iter().map(|it| sema.to_def(it)).collect();
+            // The declarations, the where clause, and the field types.
+            assert_eq!(
+                resolved,
+                [Some(a), Some(b), Some(b), Some(a), Some(a), None, Some(b)],
+                "{:?}",
+                resolved.iter().map(|it| it.map(|it| it.name(db).display(db).to_string()))
+            );
+        },
+    );
+}
+
+#[test]
+fn extern_crate_aliases_to_def() {
+    check_sema_many(
+        r#"
+//- /main.rs crate:main deps:foo,baz
+extern crate foo as bar;
+extern crate baz;
+use baz as qux;
+//- /foo.rs crate:foo
+//- /baz.rs crate:baz
+"#,
+        |sema, files| {
+            let db = sema.db;
+            let file = files[0].1.syntax();
+            let decls: Vec<_> =
+                find_all::<ast::ExternCrate>(file).iter().map(|it| sema.to_def(it)).collect();
+            let [Some(aliased), Some(plain)] = decls[..] else { unreachable!() };
+            assert_eq!(aliased.alias_or_name(db).unwrap().display(db).to_string(), "bar");
+            assert_eq!(plain.alias(db), None);
+
+            // Only the rename of the aliased declaration resolves, the one of the `use` doesn't.
+            let renames: Vec<_> =
+                find_all::<ast::Rename>(file).iter().map(|it| sema.to_def(it)).collect();
+            assert_eq!(renames, [Some(aliased), None]);
+        },
+    );
+}
+
+#[test]
+fn doc_links_to_def() {
+    check_sema(
+        r#"
+mod m {
+    pub struct Bar;
+    impl Bar {
+        pub fn method(&self) {}
+    }
+    pub fn foo() {}
+    pub mod foo {}
+}
+/// See [m::Bar] and [m::Bar::method].
+fn documented() {}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let comment = file
+                .descendants_with_tokens()
+                .filter_map(|it| it.into_token())
+                .find(|it| it.kind() == syntax::SyntaxKind::COMMENT)
+                .unwrap();
+            let owner = comment.parent().unwrap();
+            let resolve = |link: &str| match sema.resolve_doc_link(&owner, link) {
+                Some(AnyDef::Adt(it)) => format!("adt {}", it.name(db).display(db)),
+                Some(AnyDef::Function(it)) => format!("fn {}", it.name(db).display(db)),
+                Some(AnyDef::Module(it)) => format!("mod {}", it.name(db).unwrap().display(db)),
+                res => format!("{res:?}"),
+            };
+
+            assert_eq!(resolve("m::Bar"), "adt Bar");
+            assert_eq!(resolve("m::Bar::method"), "fn method");
+            // `foo` is both a function and a module, the disambiguator picks one.
+            assert_eq!(resolve("fn@m::foo"), "fn foo");
+            assert_eq!(resolve("m::foo()"), "fn foo");
+            assert_eq!(resolve("mod@m::foo"), "mod foo");
+            assert_eq!(resolve("m::Missing"), "None");
+        },
+    );
+}
+
+#[test]
+fn const_param_defaults_to_def() {
+    check_sema(
+        r#"
+struct S<const N: usize = 4, const M: usize = { 1 + 2 }>([u8; 0]);
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let owners: Vec<_> = find_all::<ast::Expr>(file)
+                .iter()
+                .map(|expr| {
+                    let owner = sema.const_param_default_owner(expr);
+                    let owner = owner.map(|it| it.name(db).display(db).to_string());
+                    (expr.to_string(), owner)
+                })
+                .collect();
+            let owner = |name: &str| Some(name.to_owned());
+            assert_eq!(
+                owners,
+                [
+                    ("4".to_owned(), owner("N")),
+                    ("{ 1 + 2 }".to_owned(), owner("M")),
+                    ("1 + 2".to_owned(), owner("M")),
+                    ("1".to_owned(), owner("M")),
+                    ("2".to_owned(), owner("M")),
+                    // The array length isn't a default value.
+                    ("0".to_owned(), None),
+                ]
+            );
+        },
+    );
+}
+
+#[test]
+fn generic_params_of_every_owner_kind() {
+    check_sema(
+        r#"
+fn f<'a, T, const N: usize>() {}
+struct S<'a, T, const N: usize>(&'a [T; N]);
+enum E<'a, T, const N: usize> { V(&'a [T; N]) }
+union U<'a, T: Copy, const N: usize> { f: &'a [T; N] }
+trait Tr<'a, T, const N: usize> {
+    type Assoc<'b, U, const M: usize>;
+    fn m<'b, U, const M: usize>();
+}
+trait Alias<'a, T, const N: usize> = Tr<'a, T, N>;
+type Ty<'a, T, const N: usize> = &'a [T; N];
+impl<'a, T, const N: usize> Tr<'a, T, N> for S<'a, T, N> {}
+"#,
+        |sema, file| {
+            let params = find_all::<ast::GenericParam>(file);
+            assert_eq!(params.len(), 30);
+            for param in params {
+                let resolved = match &param {
+                    ast::GenericParam::LifetimeParam(it) => sema.to_def(it).is_some(),
+                    ast::GenericParam::TypeParam(it) => sema.to_def(it).is_some(),
+                    ast::GenericParam::ConstParam(it) => sema.to_def(it).is_some(),
+                };
+                let owner = param.syntax().ancestors().find_map(ast::Item::cast).unwrap();
+                assert!(
+                    resolved,
+                    "`{param}` of `{}` didn't resolve",
+                    owner.syntax().first_token().unwrap()
+                );
+            }
+        },
+    );
+}
+
+#[test]
+fn attr_paths() {
+    check_sema(
+        r#"
+//- proc_macros: identity
+#[inline]
+#[proc_macros::identity]
+#[rustfmt::skip]
+fn f() {}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let resolved: Vec<_> = find_all::<ast::Attr>(file)
+                .iter()
+                .map(|attr| match sema.resolve_attr_path(attr) {
+                    Some(AnyDef::BuiltinAttr(it)) => format!("builtin {}", it.name(db)),
+                    Some(AnyDef::Macro(it)) => format!("macro {}", it.name(db).display(db)),
+                    Some(AnyDef::ToolModule(it)) => format!("tool {}", it.name(db)),
+                    def => format!("{def:?}"),
+                })
+                .collect();
+            assert_eq!(resolved, ["builtin inline", "macro identity", "tool rustfmt"]);
+        },
+    );
+}
+
+#[test]
+fn node_to_def_outcome_of_cfg_disabled_items() {
+    check_sema(
+        r#"
+#[cfg(FALSE)]
+fn f() {}
+#[cfg(FALSE)]
+mod m {
+    struct S;
+}
+fn g() {}
+"#,
+        |sema, file| {
+            let outcome = |name: &str| {
+                let name =
+                    find_all::<ast::Name>(file).into_iter().find(|it| it.text() == name).unwrap();
+                sema.node_to_def_outcome(name.syntax().parent().as_ref().unwrap())
+            };
+
+            assert_eq!(outcome("f"), ResolveOutcome::CfgDisabled);
+            assert_eq!(outcome("S"), ResolveOutcome::CfgDisabled);
+            assert!(matches!(outcome("g"), ResolveOutcome::Def(AnyDef::Function(_))));
+
+            // The body of `g` is enabled, but declares nothing.
+            let block = find_all::<ast::StmtList>(file).pop().unwrap();
+            assert_eq!(sema.node_to_def_outcome(block.syntax()), ResolveOutcome::Unresolved);
+        },
+    );
+}
+
+#[test]
+fn use_tree_leaves() {
+    check_sema(
+        r#"
+mod a {
+    pub struct b;
+    pub mod c {
+        pub fn d() {}
+    }
+}
+use a::{b, c::d};
+"#,
+        |sema, file| {
+            let tree = |path: &str| {
+                find_all::<ast::UseTree>(file)
+                    .into_iter()
+                    .find(|it| it.path().map_or(false, |it| it.to_string() == path))
+                    .unwrap()
+            };
+
+            let b = sema.resolve_use_tree_leaf(&tree("b"));
+            assert!(matches!(b, Some(AnyDef::Adt(hir::Adt::Struct(_)))), "{b:?}");
+            let d = sema.resolve_use_tree_leaf(&tree("c::d"));
+            assert!(matches!(d, Some(AnyDef::Function(_))), "{d:?}");
+            // The prefix of the group imports nothing on its own.
+            assert_eq!(sema.resolve_use_tree_leaf(&tree("a")), None);
+        },
+    );
+}
+
+#[test]
+fn single_file_containers_get_one_child_map() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => { struct FromMacro<U> { a: U } };
+}
+struct S<T> { a: T, b: u8 }
+m!();
+"#,
+        |sema, file| {
+            let expansion = sema.expand(&find(file)).unwrap();
+            let resolve_all = || {
+                for root in [file, &expansion] {
+                    for field in find_all::<ast::RecordField>(root) {
+                        assert!(sema.to_def(&field).is_some(), "unresolved field `{field}`");
+                    }
+                    for param in find_all::<ast::TypeParam>(root) {
+                        assert!(sema.to_def(&param).is_some(), "unresolved param `{param}`");
+                    }
+                }
+                sema.source_to_def_stats().misses
+            };
+
+            // The two modules (the real file and the expansion), and the fields and params of
+            // both structs, each built once.
+            let misses = resolve_all();
+            assert!(misses <= 6, "{misses} child maps built");
+            assert_eq!(resolve_all(), misses);
+        },
+    );
+}
+
+/// Resolves every definition declared in `ra_fixture`, including the ones in item macro
+/// expansions, and looks its source up again. The source has to be the node the definition was
+/// resolved from. Returns how many definitions of each kind were checked.
+fn check_source_to_def_round_trip(ra_fixture: &str) -> BTreeMap<&'static str, usize> {
+    fn source<T>(sema: &Semantics<'_, RootDatabase>, def: Option<T>) -> Option<InFile<SyntaxNode>>
+    where
+        T: HasSource,
+        T::Ast: AstNode,
+    {
+        sema.source(def?).map(|it| it.map(|it| it.syntax().clone()))
+    }
+
+    let (db, file_ids) = RootDatabase::with_many_files(ra_fixture);
+    let sema = Semantics::new(&db);
+    let mut checked = BTreeMap::new();
+    let mut failures = Vec::new();
+    for file_id in file_ids {
+        let file = sema.parse(file_id);
+        let expansions = find_all::<ast::MacroCall>(file.syntax())
+            .into_iter()
+            .filter_map(|call| sema.expand(&call));
+        let roots: Vec<_> = std::iter::once(file.syntax().clone()).chain(expansions).collect();
+        for node in roots.iter().flat_map(|root| root.descendants()) {
+            let (kind, src) = match_ast! {
+                match node {
+                    ast::Module(it) => ("Module", sema.to_def(&it).and_then(|it| {
+                        it.declaration_source(&db).map(|it| it.map(|it| it.syntax().clone()))
+                    })),
+                    ast::Fn(it) => ("Fn", source(&sema, sema.to_def(&it))),
+                    ast::Const(it) => ("Const", source(&sema, sema.to_def(&it))),
+                    ast::Static(it) => ("Static", source(&sema, sema.to_def(&it))),
+                    ast::TypeAlias(it) => ("TypeAlias", source(&sema, sema.to_def(&it))),
+                    ast::Impl(it) => ("Impl", source(&sema, sema.to_def(&it))),
+                    ast::Trait(it) => ("Trait", source(&sema, sema.to_def(&it))),
+                    ast::TraitAlias(it) => ("TraitAlias", source(&sema, sema.to_def(&it))),
+                    ast::Struct(it) => ("Struct", source(&sema, sema.to_def(&it))),
+                    ast::Union(it) => ("Union", source(&sema, sema.to_def(&it))),
+                    ast::Enum(it) => ("Enum", source(&sema, sema.to_def(&it))),
+                    ast::Variant(it) => ("Variant", source(&sema, sema.to_def(&it))),
+                    ast::RecordField(it) => ("RecordField", source(&sema, sema.to_def(&it))),
+                    ast::TupleField(it) => ("TupleField", source(&sema, sema.to_def(&it))),
+                    ast::ExternCrate(it) => ("ExternCrate", source(&sema, sema.to_def(&it))),
+                    ast::Macro(it) => ("Macro", source(&sema, sema.to_def(&it))),
+                    ast::TypeParam(it) => {
+                        ("TypeParam", source(&sema, sema.to_def(&it).map(|it| it.merge())))
+                    },
+                    ast::ConstParam(it) => {
+                        ("ConstParam", source(&sema, sema.to_def(&it).map(|it| it.merge())))
+                    },
+                    ast::LifetimeParam(it) => ("LifetimeParam", source(&sema, sema.to_def(&it))),
+                    _ => continue,
+                }
+            };
+            *checked.entry(kind).or_default() += 1;
+            let expected = (sema.hir_file_for(&node), SyntaxNodePtr::new(&node));
+            match src {
+                Some(src) if (src.file_id, SyntaxNodePtr::new(&src.value)) == expected => {}
+                src => failures.push(format!("{kind}: `{node}` round-trips to {src:?}")),
+            }
+        }
+    }
+    assert!(failures.is_empty(), "source-to-def round trips failed:\n{}", failures.join("\n"));
+    checked
+}
+
+#[test]
+fn source_to_def_round_trips() {
+    let checked = check_source_to_def_round_trip(
+        r#"
+//- /main.rs crate:main deps:dep
+extern crate dep;
+extern crate dep as renamed;
+
+mod inline {
+    pub struct Unit;
+}
+mod outline;
+
+fn f<'a, T, const N: usize>(_: &'a [T; N]) {}
+const C: u32 = 0;
+static S: u32 = 0;
+type Alias<T> = T;
+struct Record<'a, T> { field: &'a T }
+struct Tuple(u32, u64);
+union U { a: u32, b: f32 }
+enum E<T> { A, B(T), C { c: u8 } }
+trait Tr<T> {
+    const C: T;
+    type Assoc;
+    fn method(&self);
+}
+trait TrAlias<T> = Tr<T>;
+impl Tr<u8> for Tuple {
+    const C: u8 = 0;
+    type Assoc = ();
+    fn method(&self) {}
+}
+impl Tuple {
+    fn inherent() {}
+}
+macro_rules! items {
+    () => {
+        struct FromMacro { field: u8 }
+        fn from_macro() {}
+    };
+}
+items!();
+pub macro m2() {}
+
+//- /outline.rs
+pub enum Outline { V }
+
+//- /dep.rs crate:dep
+pub struct Dep;
+"#,
+    );
+    for kind in [
+        "Module",
+        "Fn",
+        "Const",
+        "Static",
+        "TypeAlias",
+        "Impl",
+        "Trait",
+        "TraitAlias",
+        "Struct",
+        "Union",
+        "Enum",
+        "Variant",
+        "RecordField",
+        "TupleField",
+        "ExternCrate",
+        "Macro",
+        "TypeParam",
+        "ConstParam",
+        "LifetimeParam",
+    ] {
+        assert!(checked.contains_key(kind), "no `{kind}` in the fixture");
+    }
+    // `items!` is expanded, so its struct and fn are checked as well.
+    assert_eq!(checked["Struct"], 5);
+    assert_eq!(checked["Fn"], 5);
+}
+
+#[test]
+fn hygienic_resolution_of_macro_identifiers() {
+    check_sema(
+        r#"
+mod m {
+    pub fn helper() -> u32 { 0 }
+    macro_rules! mac {
+        ($e:ident) => { helper() + $e };
+    }
+    pub(crate) use mac;
+}
+fn helper() -> u32 { 1 }
+fn f() -> u32 {
+    let x = 1;
+    m::mac!(x)
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let expansion = sema.expand(&find(file)).unwrap();
+            let resolve = |text: &str| {
+                let token = expansion
+                    .descendants_with_tokens()
+                    .filter_map(|it| it.into_token())
+                    .find(|it| it.text() == text)
+                    .unwrap();
+                match sema.resolve_hygienic(&token) {
+                    Some((origin, PathResolution::Def(hir::ModuleDef::Function(it)))) => {
+                        let module = it.module(db).name(db).map(|it| it.display(db).to_string());
+                        format!("{origin:?} fn in {module:?}")
+                    }
+                    Some((origin, PathResolution::Local(it))) => {
+                        format!("{origin:?} local {}", it.name(db).display(db))
+                    }
+                    res => format!("{res:?}"),
+                }
+            };
+
+            // `helper` resolves next to the macro, not to the function at the call site.
+            assert_eq!(resolve("helper"), r#"DefSite fn in Some("m")"#);
+            assert_eq!(resolve("x"), "CallSite local x");
+        },
+    );
+}
+
+#[test]
+fn method_calls_to_def() {
+    check_sema(
+        r#"
+trait Tr {
+    fn by_trait(&self);
+}
+struct S;
+impl S {
+    fn inherent(&self) {}
+}
+impl Tr for S {
+    fn by_trait(&self) {}
+}
+fn f(s: S) {
+    s.inherent();
+    s.by_trait();
+}
+"#,
+        |sema, file| {
+            let fns = find_all::<ast::Fn>(file);
+            let [trait_decl, inherent, impl_decl, ..] = &fns[..] else { unreachable!() };
+            let [inherent_call, trait_call] = &find_all::<ast::MethodCallExpr>(file)[..] else {
+                unreachable!()
+            };
+
+            assert_eq!(sema.to_def(inherent_call), sema.to_def(inherent));
+            // Unlike `resolve_method_call`, this doesn't look for the implementation.
+            assert_eq!(sema.to_def(trait_call), sema.to_def(trait_decl));
+            assert_eq!(sema.resolve_method_call(trait_call), sema.to_def(impl_decl));
+        },
+    );
+}
+
+#[test]
+fn resolve_all_in_range_reports_each_def_once() {
+    check_sema_many(
+        r#"
+struct S { field: u32 }
+impl S {
+    fn method(&self) -> u32 { self.field }
+}
+fn f(s: S) -> u32 {
+    let local = s.method();
+    local + s.field
+}
+"#,
+        |sema, files| {
+            let (file_id, file) = &files[0];
+            let text = file.syntax().text().to_string();
+            let start = TextSize::try_from(text.find("fn f").unwrap()).unwrap();
+            let range = TextRange::new(start, file.syntax().text_range().end());
+
+            let resolved: Vec<_> = sema
+                .resolve_all_in_range(*file_id, range)
+                .into_iter()
+                .map(|(range, def)| {
+                    let kind = format!("{def:?}");
+                    format!("{} {}", &text[range], kind.split('(').next().unwrap())
+                })
+                .collect();
+            // `u32` is a builtin type, which isn't an `AnyDef`.
+            assert_eq!(
+                resolved,
+                ["f Function", "s Local", "S Adt", "local Local", "method Function", "field Field"]
+            );
+        },
+    );
+}
+
+#[test]
+fn ident_pats_resolve_after_an_edit() {
+    let (mut db, file_id) = RootDatabase::with_single_file(
+        r#"
+const C: u8 = 0;
+fn f(x: u8) {
+    let C = x;
+}
+"#,
+    );
+    let resolve_all = |db: &RootDatabase| {
+        let sema = Semantics::new(db);
+        let file = sema.parse(file_id);
+        find_all::<ast::IdentPat>(file.syntax())
+            .iter()
+            .map(|pat| {
+                let name = pat.name().unwrap().to_string();
+                match sema.resolve_ident_pat(pat) {
+                    Some(Either::Left(_)) => format!("{name} local"),
+                    Some(Either::Right(_)) => format!("{name} const"),
+                    None => format!("{name} unresolved"),
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(resolve_all(&db), ["x local", "C const"]);
+
+    // The patterns move around in the body, so their ids in the new source map differ from the
+    // ones seen before.
+    db.set_file_text(
+        file_id,
+        r#"
+fn f(x: u8) {
+    let (a, b) = (x, x);
+    let C = a;
+    let y = b;
+}
+"#,
+    );
+    assert_eq!(resolve_all(&db), ["x local", "a local", "b local", "C local", "y local"]);
+}
+
+#[test]
+fn defs_from_macros() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => {
+        struct Generated { field: u8 }
+        fn generated() { let local = 0; }
+    };
+}
+m!();
+struct Written { field: u8 }
+fn written() { let local = 0; }
+"#,
+        |sema, file| {
+            let db = sema.db;
+            // The body of `m` is a token tree, so only the written items are nodes of the file.
+            let from_macro = |root: &SyntaxNode| {
+                let nodes = [
+                    find::<ast::Struct>(root).syntax().clone(),
+                    find::<ast::RecordField>(root).syntax().clone(),
+                    find::<ast::Fn>(root).syntax().clone(),
+                    find::<ast::IdentPat>(root).syntax().clone(),
+                ];
+                nodes.map(|it| sema.node_to_def(&it).unwrap().is_from_macro(db))
+            };
+
+            assert_eq!(from_macro(file), [false; 4]);
+            assert_eq!(from_macro(&sema.expand(&find(file)).unwrap()), [true; 4]);
+        },
+    );
+}
+
+#[test]
+fn defs_inside_a_macro_expansion() {
+    check_sema(
+        r#"
+macro_rules! m {
+    () => {
+        fn generated() {}
+    };
+}
+m!();
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let macro_file = sema.to_def(&find::<ast::MacroCall>(file)).unwrap().as_macro_file();
+            let expanded = sema.expand_and_map(macro_file);
+            let func = find::<ast::Fn>(&expanded.value);
+
+            let Some(AnyDef::Function(def)) =
+                sema.expansion_node_to_def(macro_file, SyntaxNodePtr::new(func.syntax()))
+            else {
+                panic!("`generated` doesn't resolve to a function")
+            };
+            assert_eq!(def.name(db).display(db).to_string(), "generated");
+            // The expansion was registered with `sema`, so its nodes resolve directly, too.
+            assert_eq!(sema.to_def(&func), Some(def));
+            let module = sema.file_to_module_def(sema.hir_file_for(file).file_id().unwrap());
+            assert!(module.unwrap().declarations(db).contains(&hir::ModuleDef::Function(def)));
+        },
+    );
+}
+
+#[test]
+fn tokens_to_def() {
+    check_sema(
+        r#"
+macro_rules! m {
+    ($e:expr) => { $e };
+}
+struct Foo;
+fn f() {
+    let x = 0;
+    m!(x);
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            let resolve = |text: &str, nth: usize| {
+                let token = file
+                    .descendants_with_tokens()
+                    .filter_map(|it| it.into_token())
+                    .filter(|it| it.text() == text)
+                    .nth(nth)
+                    .unwrap_or_else(|| panic!("no token `{text}`"));
+                match sema.token_to_def(&token) {
+                    Some(AnyDef::Adt(it)) => format!("adt {}", it.name(db).display(db)),
+                    Some(AnyDef::Function(it)) => format!("fn {}", it.name(db).display(db)),
+                    Some(AnyDef::Local(it)) => format!("local {}", it.name(db).display(db)),
+                    res => format!("{res:?}"),
+                }
+            };
+
+            assert_eq!(resolve("Foo", 0), "adt Foo");
+            assert_eq!(resolve("x", 0), "local x");
+            // Tokens that aren't part of a name resolve to the item around them.
+            assert_eq!(resolve("0", 0), "fn f");
+            assert_eq!(resolve("x", 1), "fn f");
+        },
+    );
+}
+
+#[test]
+fn variants_to_variant_def() {
+    check_sema(
+        r#"
+enum E {
+    A,
+    B(u32),
+    C { x: u8, y: u8 },
+}
+"#,
+        |sema, file| {
+            let db = sema.db;
+            for variant in find_all::<ast::Variant>(file) {
+                let def = sema.to_variant_def(&variant).unwrap();
+                assert!(
+                    matches!(def, hir::VariantDef::Variant(it) if sema.to_def(&variant) == Some(it))
+                );
+                let fields =
+                    def.fields(db).into_iter().map(|it| it.name(db).display(db).to_string());
+                let expected = match variant.name().unwrap().text().as_str() {
+                    "A" => vec![],
+                    "B" => vec!["0"],
+                    _ => vec!["x", "y"],
+                };
+                assert_eq!(fields.collect::<Vec<_>>(), expected, "{variant}");
+            }
+        },
+    );
+}
+
+#[test]
+fn cyclic_path_modules_terminate() {
+    check_sema_many(
+        r#"
+//- /lib.rs
+mod foo;
+//- /foo.rs
+#[path = "./bar.rs"]
+mod bar;
+mod inline {
+    mod nested {}
+}
+//- /bar.rs
+#[path = "./foo.rs"]
+mod foo;
+"#,
+        |sema, files| {
+            let db = sema.db;
+            for (_, file) in files {
+                for module in find_all::<ast::Module>(file.syntax()) {
+                    let def = sema.to_def(&module).unwrap_or_else(|| panic!("unresolved {module}"));
+                    assert_eq!(
+                        def.name(db).map(|it| it.display(db).to_string()),
+                        module.name().map(|it| it.to_string()),
+                    );
+                    // Resolving again goes through the cache and must not trip over the
+                    // declarations left from the first lookup.
+                    assert_eq!(sema.to_def(&module), Some(def));
+                }
+            }
+        },
+    );
+}
+
+#[test]
+fn assoc_item_lists_to_owner() {
+    check_sema(
+        r#"
+trait Tr {
+    fn f();
+}
+struct S;
+impl Tr for S {
+    fn f() {}
+}
+impl S {}
+"#,
+        |sema, file| {
+            let lists = find_all::<ast::AssocItemList>(file);
+            let owners = lists.iter().map(|it| sema.assoc_item_list_owner(it)).collect::<Vec<_>>();
+            let trait_ = sema.to_def(&find::<ast::Trait>(file)).unwrap();
+            let impls = find_all::<ast::Impl>(file)
+                .iter()
+                .map(|it| sema.to_def(it).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                owners,
+                [
+                    Some(AnyDef::Trait(trait_)),
+                    Some(AnyDef::Impl(impls[0])),
+                    Some(AnyDef::Impl(impls[1])),
+                ]
+            );
+        },
+    );
+}
+
+#[test]
+fn format_args_captures_to_def() {
+    check_sema(
+        r#"
+//- minicore: fmt
+const C: u32 = 0;
+fn f() {
+    let x = 1;
+    core::format_args!("{x} {C} {}", 0);
+}
+"#,
+        |sema, file| {
+            let x = sema.to_def(&find::<ast::IdentPat>(file)).unwrap();
+            let expansion = sema.expand(&find::<ast::MacroCall>(file)).unwrap();
+            let format_args = find::<ast::FormatArgsExpr>(&expansion);
+            let resolve =
+                |offset: u32| sema.resolve_format_args_capture(&format_args, offset.into());
+
+            assert_eq!(resolve(1), Some((TextRange::new(1.into(), 2.into()), x)));
+            assert_eq!(resolve(2), Some((TextRange::new(1.into(), 2.into()), x)));
+            // `C` is a constant, not a local.
+            assert_eq!(resolve(5), None);
+            // Positional arguments and the template's literal text aren't captures.
+            assert_eq!(resolve(9), None);
+            assert_eq!(resolve(3), None);
+        },
+    );
+}
+
+#[test]
+fn resolve_or_expand_items() {
+    check_sema(
+        r#"
+//- proc_macros: identity
+//- minicore: derive, clone
+#[derive(Clone)]
+struct Derived;
+struct Plain;
+#[proc_macros::identity]
+struct Attributed;
+"#,
+        |sema, file| {
+            let item = |name: &str| {
+                let strukt = find_named::<ast::Struct>(file, name);
+                (sema.resolve_or_expand(&strukt.clone().into()), strukt)
+            };
+
+            // Derives add items next to the struct but leave the struct itself alone.
+            for name in ["Derived", "Plain"] {
+                let (res, strukt) = item(name);
+                let def = sema.to_def(&strukt).unwrap();
+                assert_eq!(res, Some(ItemResolution::Def(AnyDef::Adt(def.into()))), "{name}");
+            }
+
+            let (res, _) = item("Attributed");
+            let Some(ItemResolution::MacroCall(macro_file)) = res else {
+                panic!("unexpected resolution: {res:?}");
+            };
+            let expansion = sema.parse_or_expand(macro_file.into());
+            assert_eq!(find::<ast::Struct>(&expansion).name().unwrap().text(), "Attributed");
+        },
+    );
+}
+
+#[test]
+fn names_and_name_refs_to_def() {
+    check_sema(
+        r#"
+struct Foo {
+    field: u32,
+}
+impl Foo {
+    fn method(&self) {}
+}
+fn f(foo: Foo) {
+    let _: Foo = foo;
+    foo.field;
+    foo.method();
+}
+"#,
+        |sema, file| {
+            let strukt = sema.to_def(&find::<ast::Struct>(file)).unwrap();
+            let field = sema.to_def(&find::<ast::RecordField>(file)).unwrap();
+            let method = sema.to_def(&find_named::<ast::Fn>(file, "method")).unwrap();
+            let param = sema.to_def(&find::<ast::IdentPat>(file)).unwrap();
+
+            let names = find_all::<ast::Name>(file);
+            let name = |text: &str| names.iter().find(|it| it.text() == text).unwrap();
+            assert_eq!(sema.name_to_def(name("Foo")), Some(AnyDef::Adt(strukt.into())));
+            assert_eq!(sema.name_to_def(name("field")), Some(AnyDef::Field(field)));
+            assert_eq!(sema.name_to_def(name("foo")), Some(AnyDef::Local(param)));
+
+            for name_ref in find_all::<ast::NameRef>(file) {
+                let expected = match name_ref.text().as_str() {
+                    "Foo" => Some(AnyDef::Adt(strukt.into())),
+                    "foo" => Some(AnyDef::Local(param)),
+                    "field" => Some(AnyDef::Field(field)),
+                    "method" => Some(AnyDef::Function(method)),
+                    // Builtins aren't declared anywhere.
+                    "u32" => None,
+                    text => panic!("unexpected name ref `{text}`"),
+                };
+                assert_eq!(sema.name_ref_to_def(&name_ref), expected, "{name_ref}");
+            }
+        },
+    );
+}
+
+#[test]
+fn methods_to_their_impl() {
+    check_sema(
+        r#"
+trait Tr {
+    fn declared(&self);
+}
+struct S;
+impl S {
+    fn inherent(&self) {}
+}
+impl Tr for S {
+    fn declared(&self) {}
+}
+fn free() {}
+"#,
+        |sema, file| {
+            let impls = find_all::<ast::Impl>(file)
+                .iter()
+                .map(|it| sema.to_def(it).unwrap())
+                .collect::<Vec<_>>();
+            let fns = find_all::<ast::Fn>(file);
+            let impl_of = |nth: usize| sema.resolve_impl_for_method(&fns[nth]);
+
+            // The trait's declaration, the inherent method, the trait impl's method and `free`.
+            assert_eq!(fns.len(), 4);
+            assert_eq!(impl_of(0), None);
+            assert_eq!(impl_of(1), Some(impls[0]));
+            assert_eq!(impl_of(2), Some(impls[1]));
+            assert_eq!(impl_of(3), None);
+        },
+    );
+}
+
+/// A file with `n` structs, enums and impls, and their fields, variants and methods.
+fn items_fixture(n: usize) -> String {
+    (0..n)
+        .map(|i| {
+            format!(
+                "struct S{i} {{ a: u8, b: u16 }}\n\
+                 enum E{i} {{ A, B(u8) }}\n\
+                 impl S{i} {{ fn m{i}(&self) -> u8 {{ let x = self.a; x }} }}\n"
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn nodes_to_defs_matches_node_to_def() {
+    let (db, file_id) = RootDatabase::with_single_file(&items_fixture(3));
+    let (expected, per_node_stats) = {
+        let sema = Semantics::new(&db);
+        let file = sema.parse(file_id);
+        let defs: Vec<_> = file.syntax().descendants().map(|it| sema.node_to_def(&it)).collect();
+        (defs, sema.source_to_def_stats())
+    };
+
+    let sema = Semantics::new(&db);
+    let file = sema.parse(file_id);
+    let nodes: Vec<_> = file.syntax().descendants().collect();
+    assert_eq!(sema.nodes_to_defs(&nodes), expected);
+    // Every child map is built once either way, but the batch fetches it once per container
+    // rather than once per node.
+    let stats = sema.source_to_def_stats();
+    assert_eq!(stats.misses, per_node_stats.misses);
+    assert!(stats.hits < per_node_stats.hits, "{stats:?} vs {per_node_stats:?}");
+}
+
+#[test]
+fn benchmark_nodes_to_defs() {
+    if test_utils::skip_slow_tests() {
+        return;
+    }
+    let (db, file_id) = RootDatabase::with_single_file(&items_fixture(1000));
+
+    let per_node = {
+        let sema = Semantics::new(&db);
+        let file = sema.parse(file_id);
+        let _b = test_utils::bench("node_to_def per node");
+        file.syntax().descendants().map(|node| sema.node_to_def(&node)).collect::<Vec<_>>()
+    };
+    let batched = {
+        let sema = Semantics::new(&db);
+        let file = sema.parse(file_id);
+        let nodes: Vec<_> = file.syntax().descendants().collect();
+        let _b = test_utils::bench("nodes_to_defs");
+        sema.nodes_to_defs(&nodes)
+    };
+    assert_eq!(per_node, batched);
+}
+
+#[test]
+fn benchmark_generic_params_to_def() {
+    if test_utils::skip_slow_tests() {
+        return;
+    }
+    let lifetimes = (0..500).map(|i| format!("'l{i}")).collect::<Vec<_>>().join(", ");
+    let types = (0..500).map(|i| format!("T{i}")).collect::<Vec<_>>().join(", ");
+    let (db, file_id) =
+        RootDatabase::with_single_file(&format!("fn f<{lifetimes}, {types}>() {{}}"));
+    let sema = Semantics::new(&db);
+    let file = sema.parse(file_id);
+    let params = find_all::<ast::GenericParam>(file.syntax());
+
+    let _b = test_utils::bench("generic params to def");
+    let resolved = params
+        .iter()
+        .filter(|param| match param {
+            ast::GenericParam::LifetimeParam(it) => sema.to_def(it).is_some(),
+            ast::GenericParam::TypeParam(it) => sema.to_def(it).is_some(),
+            ast::GenericParam::ConstParam(it) => sema.to_def(it).is_some(),
+        })
+        .count();
+    assert_eq!(resolved, 1000);
+    // The function is found once, every other param reuses its container.
+    assert_eq!(sema.source_to_def_stats().container_searches, 1);
+}
+
+#[test]
+fn benchmark_containers_in_expressions() {
+    if test_utils::skip_slow_tests() {
+        return;
+    }
+    // Each struct is declared in a block nested in the binary expressions of its predecessors.
+    let depth = 200;
+    let open: String = (0..depth).map(|i| format!("({{ struct S{i}; 0 }} + ")).collect();
+    let close = ")".repeat(depth);
+    let (db, file_id) =
+        RootDatabase::with_single_file(&format!("fn f() -> u32 {{ {open}0{close} }}"));
+    let sema = Semantics::new(&db);
+    let file = sema.parse(file_id);
+    let structs = find_all::<ast::Struct>(file.syntax());
+
+    let _b = test_utils::bench("containers in expressions");
+    let resolved = structs.iter().filter(|it| sema.to_def(*it).is_some()).count();
+    assert_eq!(resolved, depth);
+}
+
+#[test]
+fn dump_of_a_module_container() {
+    check_sema(
+        r#"
+fn f() {}
+struct S;
+"#,
+        |sema, file| {
+            let func = find::<ast::Fn>(file);
+            let strukt = find::<ast::Struct>(file);
+            let dump = sema.dump_source_to_def_container(func.syntax()).unwrap();
+
+            let section = |name: &str| {
+                let start = dump.find(&format!("{name}:\n")).unwrap_or_else(|| panic!("{dump}"));
+                dump[start..].lines().skip(1).take_while(|it| it.starts_with("    ")).count()
+            };
+            assert_eq!(section(std::any::type_name::<ast::Fn>()), 1);
+            assert_eq!(section(std::any::type_name::<ast::Struct>()), 1);
+            for range in [func.syntax().text_range(), strukt.syntax().text_range()] {
+                assert!(dump.contains(&format!("    {range:?} => ")), "{dump}");
+            }
+            assert!(dump.contains("FunctionId(") && dump.contains("StructId("), "{dump}");
+        },
+    );
+}
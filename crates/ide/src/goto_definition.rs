@@ -80,6 +80,13 @@ pub(crate) fn goto_definition(
                 }
             }
 
+            if token.kind() == T![*] {
+                if let Some(use_tree) = ast::UseTree::cast(parent.clone()) {
+                    let module = sema.resolve_use_glob(&use_tree)?;
+                    return Some(def_to_nav(db, Definition::Module(module)));
+                }
+            }
+
             if ast::TokenTree::can_cast(parent.kind()) {
                 if let Some(x) = try_lookup_macro_def_in_macro_use(sema, token) {
                     return Some(vec![x]);
@@ -318,6 +325,43 @@ fn function() {
     identity!(Struct$0 { field: 0 });
 }
 
+"#,
+        )
+    }
+
+    #[test]
+    fn goto_def_for_local_item_in_attr_invoc() {
+        check(
+            r#"
+//- proc_macros: identity
+#[proc_macros::identity]
+fn function() {
+    struct Local;
+         //^^^^^
+    let _ = Local$0;
+}
+"#,
+        )
+    }
+
+    #[test]
+    fn goto_def_for_type_alias_type_param() {
+        check(
+            r#"
+struct Wrapper<T>(T);
+type Alias<U> = Wrapper<U$0>;
+         //^
+"#,
+        )
+    }
+
+    #[test]
+    fn goto_def_for_trait_alias_type_param() {
+        check(
+            r#"
+trait Bar<T> {}
+trait Alias<T> = Bar<T$0>;
+          //^
 "#,
         )
     }
@@ -348,6 +392,81 @@ extern crate std as abc$0;
         )
     }
 
+    #[test]
+    fn goto_def_for_path_type_with_generic_args() {
+        check(
+            r#"
+struct Foo<T>(T);
+     //^^^
+fn f(_: Foo$0<u8>) {}
+"#,
+        );
+        check(
+            r#"
+type Alias<T> = (T,);
+   //^^^^^
+fn f(_: Alias$0<u8>) {}
+"#,
+        );
+        check(
+            r#"
+trait Tr<T> {}
+    //^^
+fn f(_: &dyn Tr$0<u8>) {}
+"#,
+        );
+    }
+
+    #[test]
+    fn goto_def_for_extern_crate_self_alias() {
+        check(
+            r#"
+//- /main.rs crate:main
+extern crate self as foo$0;
+//^file
+"#,
+        )
+    }
+
+    #[test]
+    fn goto_def_for_const_param_in_where_clause_operand() {
+        check(
+            r#"
+struct Buf<const N: usize>
+               //^
+where
+    [(); N$0]: Sized;
+"#,
+        )
+    }
+
+    #[test]
+    fn goto_def_for_macro_call_in_type_position() {
+        check(
+            r#"
+macro_rules! ty { () => { u32 } }
+           //^^
+fn f() -> ty$0!() { 0 }
+"#,
+        )
+    }
+
+    #[test]
+    fn goto_def_for_macro_call_in_pattern_position() {
+        check(
+            r#"
+macro_rules! pat { () => { 0 } }
+           //^^^
+fn f(x: u32) {
+    match x {
+        pat$0!() => (),
+        _ => (),
+    }
+}
+"#,
+        )
+    }
+
     #[test]
     fn goto_def_in_items() {
         check(
@@ -414,6 +533,50 @@ mod $0foo;
         );
     }
 
+    #[test]
+    fn goto_def_for_use_glob() {
+        check(
+            r#"
+mod foo {
+    pub mod bar {}
+          //^^^
+}
+use foo::bar::$0*;
+"#,
+        );
+        check(
+            r#"
+mod foo {
+    pub mod bar {}
+          //^^^
+}
+use crate::{foo::bar::$0*};
+"#,
+        );
+        check(
+            r#"
+mod foo {
+  //^^^
+    use self::$0*;
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn goto_def_for_use_glob_into_extern_crate() {
+        check(
+            r#"
+//- /lib.rs crate:main deps:foo
+use foo::$0*;
+
+//- /foo/lib.rs crate:foo
+// empty
+//^file
+"#,
+        );
+    }
+
     #[test]
     fn goto_def_for_macros() {
         check(
@@ -563,6 +726,20 @@ use foo as bar$0;
         );
     }
 
+    #[test]
+    fn goto_def_for_use_alias_in_group() {
+        check(
+            r#"
+mod foo {
+    pub struct Baz;
+             //^^^
+    pub struct Qux;
+}
+use foo::{Qux, {Baz as bar$0}};
+"#,
+        );
+    }
+
     #[test]
     fn goto_def_for_use_alias_foo_macro() {
         check(
@@ -595,6 +772,37 @@ fn bar(foo: &Foo) {
         );
     }
 
+    #[test]
+    fn goto_def_for_trait_method_call() {
+        check(
+            r#"
+trait Tr {
+    fn frobnicate(&self);
+     //^^^^^^^^^^
+}
+
+fn bar<T: Tr>(t: &T) {
+    t.frobnicate$0();
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn goto_def_for_field_with_default_value() {
+        // Default field values aren't supported by the parser yet, make sure the field still
+        // resolves.
+        check(
+            r#"
+struct S { x: u32 = 5 }
+         //^
+fn f(s: S) {
+    s.x$0;
+}
+"#,
+        );
+    }
+
     #[test]
     fn goto_def_for_fields() {
         check(
@@ -674,6 +882,20 @@ fn bar() {
         );
     }
 
+    #[test]
+    fn goto_def_for_tuple_struct_constructor_call() {
+        check(
+            r#"
+struct Foo(u32);
+     //^^^
+
+fn bar() {
+    let foo = Foo$0(0);
+}
+"#,
+        );
+    }
+
     #[test]
     fn goto_def_for_ufcs_inherent_methods() {
         check(
@@ -804,6 +1026,18 @@ impl Make for Foo {
         );
     }
 
+    #[test]
+    fn goto_definition_on_self_in_trait() {
+        check(
+            r#"
+trait Make {
+    //^^^^
+    fn new() -> Self$0;
+}
+"#,
+        );
+    }
+
     #[test]
     fn goto_def_when_used_on_definition_name_itself() {
         check(
@@ -1220,6 +1454,17 @@ fn baz() {
         )
     }
 
+    #[test]
+    fn goto_def_for_tool_module() {
+        // Tool modules are resolved, but have no source to navigate to.
+        check_unresolved(
+            r#"
+#[rustfmt$0::skip]
+fn f() {}
+"#,
+        );
+    }
+
     #[test]
     fn unknown_assoc_ty() {
         check_unresolved(
@@ -1345,6 +1590,29 @@ fn foo<'foobar>(_: &'foobar$0 ()) {
         )
     }
 
+    #[test]
+    fn goto_generic_params_of_assoc_type() {
+        check(
+            r#"
+trait T {
+    type A<'x>
+         //^^
+    where
+        Self: 'x$0;
+}
+"#,
+        );
+        check(
+            r#"
+//- minicore: from
+trait T {
+    type B<U>: Into<U$0>;
+         //^
+}
+"#,
+        );
+    }
+
     #[test]
     fn goto_lifetime_param_decl_nested() {
         check(
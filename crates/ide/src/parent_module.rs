@@ -111,6 +111,25 @@ mod $0bar;
         );
     }
 
+    #[test]
+    fn test_resolve_parent_module_of_nested_out_of_line_module() {
+        check(
+            r#"
+//- /lib.rs
+mod foo;
+//- /foo.rs
+mod bar;
+//- /foo/bar.rs
+mod baz;
+  //^^^
+//- /foo/bar/baz.rs
+fn f() {
+    $0
+}
+"#,
+        );
+    }
+
     #[test]
     fn test_resolve_parent_module_for_inline() {
         check(
@@ -631,6 +631,48 @@ fn main() {
         );
     }
 
+    #[test]
+    fn test_find_all_refs_for_binding_in_variant_pat() {
+        check(
+            r#"
+enum E { A, B(u8) }
+use E::*;
+fn f(e: E) -> u8 {
+    match e {
+        B(x$0) => x,
+        A => 0,
+    }
+}
+"#,
+            expect![[r#"
+                x Local FileId(0) 73..74 73..74
+
+                FileId(0) 79..80 read
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_find_all_refs_for_unit_variant_ident_pat() {
+        check(
+            r#"
+enum E { A, B(u8) }
+use E::*;
+fn f(e: E) -> u8 {
+    match e {
+        B(x) => x,
+        A$0 => 0,
+    }
+}
+"#,
+            expect![[r#"
+                A Variant FileId(0) 9..10 9..10
+
+                FileId(0) 90..91
+            "#]],
+        );
+    }
+
     #[test]
     fn search_filters_by_range() {
         check(
@@ -824,6 +866,25 @@ fn f() {
         );
     }
 
+    #[test]
+    fn test_find_all_refs_extern_block_fn() {
+        check(
+            r#"
+extern "C" {
+    fn ffi$0(x: u32);
+}
+fn main() {
+    unsafe { ffi(0) };
+}
+"#,
+            expect![[r#"
+                ffi Function FileId(0) 17..32 20..23
+
+                FileId(0) 60..63
+            "#]],
+        );
+    }
+
     #[test]
     fn test_find_all_refs_decl_module() {
         check(
@@ -971,6 +1032,78 @@ fn foo() {
         );
     }
 
+    #[test]
+    fn test_find_all_refs_item_in_const_initializer() {
+        check(
+            r#"
+const C: () = {
+    struct Local$0;
+    let _ = Local;
+};
+"#,
+            expect![[r#"
+                Local Struct FileId(0) 20..33 27..32
+
+                FileId(0) 46..51
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_find_all_refs_local_in_async_block() {
+        check(
+            r#"
+fn f() {
+    let _ = async {
+        let x$0 = 1;
+        x
+    };
+}
+"#,
+            expect![[r#"
+                x Local FileId(0) 41..42 41..42
+
+                FileId(0) 56..57 read
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_find_all_refs_foreign_static() {
+        check(
+            r#"
+extern "C" {
+    static S$0: u32;
+}
+fn f() -> u32 {
+    unsafe { S }
+}
+"#,
+            expect![[r#"
+                S Static FileId(0) 17..31 24..25
+
+                FileId(0) 63..64
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_find_all_refs_local_macro_def() {
+        check(
+            r#"
+fn f() {
+    macro_rules! m$0 { () => {} }
+    m!();
+}
+"#,
+            expect![[r#"
+                m Macro FileId(0) 13..40 26..27
+
+                FileId(0) 45..46
+            "#]],
+        );
+    }
+
     #[test]
     fn test_basic_highlight_read_write() {
         check(
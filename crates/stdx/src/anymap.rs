@@ -126,6 +126,13 @@ impl<A: ?Sized + Downcast> Map<A> {
         self.raw.get(&TypeId::of::<T>()).map(|any| unsafe { any.downcast_ref_unchecked::<T>() })
     }
 
+    /// Returns a mutable reference to the value stored in the collection for the type `T`,
+    /// if it exists.
+    #[inline]
+    pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+        self.raw.get_mut(&TypeId::of::<T>()).map(|any| unsafe { any.downcast_mut_unchecked::<T>() })
+    }
+
     /// Gets the entry for the given type in the collection for in-place manipulation
     #[inline]
     pub fn entry<T: IntoBox<A>>(&mut self) -> Entry<'_, A, T> {